@@ -4,55 +4,59 @@ use actix_web::{
 use actix_web_lab::respond::Html;
 use async_graphql::{
     http::{playground_source, GraphQLPlaygroundConfig},
-    Data, Schema,
+    Data, ErrorExtensions, Pos, Schema,
 };
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
 //use serde_derive::{Deserialize, Serialize};
 use mongodb::options::Credential;
 use mongodb::{options::ClientOptions, Client};
-use rdkafka::consumer::StreamConsumer;
-use rdkafka::error::KafkaError;
-use serde::{Deserialize, Serialize};
-use std::env;
-use rdkafka::message::{Header, OwnedHeaders};
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use rdkafka::util::get_rdkafka_version;
-
-use crate::bootstrap::schema::model::Hand;
+use once_cell::sync::Lazy;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
 pub mod schema {
     include!("./schema/mod.rs");
 }
 use schema::{
-    mutation::MutationRoot, HandToken, PokerSchema, QueryRoot, SubscriptionRoot, TableToken,
-    UserToken,
+    auth::{Accounts, AuthError, TokenSigner},
+    event_bus::{InMemoryEventBus, KafkaEventBus, SharedEventBus},
+    mutation::MutationRoot,
+    scheduler::{Scheduler, SharedScheduler},
+    shuffler::{SeededShuffler, SharedShuffler, StackedDeck},
+    storage::{
+        memory::{InMemoryHandLogRepository, InMemoryHandRepository, InMemoryTableRepository},
+        mongo::{MongoHandLogRepository, MongoHandRepository, MongoTableRepository},
+        SharedHandLogRepository, SharedHandRepository, SharedTableRepository,
+    },
+    HandToken, PokerSchema, QueryRoot, SubscriptionRoot, TableToken, UserToken,
 };
 
-use rdkafka::client::ClientContext;
-use rdkafka::config::{ClientConfig, RDKafkaLogLevel};
-use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer, ConsumerContext, Rebalance};
-use rdkafka::error::KafkaResult;
-use rdkafka::message::{Headers, Message};
-use rdkafka::topic_partition_list::TopicPartitionList;
-
-// A context can be used to change the behavior of producers and consumers by adding callbacks
-// that will be executed by librdkafka.
-// This particular context sets up custom callbacks to log rebalancing events.
-struct ConsumerTestContext;
+/// Signs and checks user tokens with the same key on every worker.
+static TOKENS: Lazy<TokenSigner> = Lazy::new(|| TokenSigner::new(auth_key().as_bytes()));
 
-impl ClientContext for ConsumerTestContext {}
+pub fn tokens() -> &'static TokenSigner {
+    &TOKENS
+}
 
-impl ConsumerContext for ConsumerTestContext {
-    fn commit_callback(&self, result: KafkaResult<()>, _offsets: &TopicPartitionList) {
-        println!("Committing offsets: {:?}", result);
+/// The user a `x-user-token` was issued to. No token signs nobody in, but
+/// a bad one is refused.
+fn verify_user_token(token: Option<&str>) -> Result<Option<UserToken>, AuthError> {
+    match token {
+        Some(token) => {
+            let user_id = tokens().verify(token, chrono::Utc::now().timestamp())?;
+            Ok(Some(UserToken(user_id.to_string())))
+        }
+        None => Ok(None),
     }
 }
-fn get_user_token_from_headers(headers: &HeaderMap) -> Option<UserToken> {
+
+fn get_user_token_from_headers(headers: &HeaderMap) -> Result<Option<UserToken>, AuthError> {
     let token = headers
         .get("x-user-token")
-        .and_then(|value| value.to_str().map(|s| UserToken(s.to_string())).ok());
-    println!("get user token from headers");
-    println!("token: {:?}", token);
-    token
+        .map(|value| value.to_str().map_err(|_| AuthError::InvalidToken))
+        .transpose()?;
+    verify_user_token(token)
 }
 
 fn get_table_token_from_headers(headers: &HeaderMap) -> Option<TableToken> {
@@ -68,17 +72,13 @@ fn get_hand_token_from_headers(headers: &HeaderMap) -> Option<HandToken> {
 }
 
 pub async fn on_connection_init(value: serde_json::Value) -> async_graphql::Result<Data> {
-    println!("on_connection_init");
-    println!("{:?}", value);
-
     let mut data = Data::default();
 
-    if let Some(user_token) = value
+    let user_token = value
         .get("x-user-token")
-        .and_then(|user_token| user_token.as_str())
-    {
-        println!("insert user token");
-        data.insert(UserToken(user_token.to_string()));
+        .and_then(|user_token| user_token.as_str());
+    if let Some(user_token) = verify_user_token(user_token).map_err(|e| e.extend())? {
+        data.insert(user_token);
     }
 
     if let Some(table_token) = value
@@ -95,8 +95,6 @@ pub async fn on_connection_init(value: serde_json::Value) -> async_graphql::Resu
         data.insert(HandToken(hand_token.to_string()));
     }
 
-    //TODO: handle missing tokens
-
     Ok(data)
 }
 
@@ -120,8 +118,13 @@ async fn index(
     if let Some(token) = get_table_token_from_headers(req.headers()) {
         request = request.data(token);
     }
-    if let Some(token) = get_user_token_from_headers(req.headers()) {
-        request = request.data(token);
+    match get_user_token_from_headers(req.headers()) {
+        Ok(Some(token)) => request = request.data(token),
+        Ok(None) => {}
+        Err(e) => {
+            let error = e.extend().into_server_error(Pos::default());
+            return async_graphql::Response::from_errors(vec![error]).into();
+        }
     }
     if let Some(token) = get_hand_token_from_headers(req.headers()) {
         request = request.data(token);
@@ -129,7 +132,7 @@ async fn index(
     schema.execute(request).await.into()
 }
 
-/// GraphQL endpoint
+// GraphQL endpoint
 /* #[route("/graphql", method = "GET", method = "POST")]
 async fn graphql(schema: web::Data<PokerSchema>, req: GraphQLRequest) -> GraphQLResponse {
     schema.execute(req.into_inner()).await.into()
@@ -146,87 +149,131 @@ async fn graphql_playground() -> impl Responder {
 pub fn kafka_seed() -> String {
     std::env::var("KAFKA_SEED").unwrap_or_else(|_| {
         let kafka_seed = "127.0.0.1:9092".to_string();
-        ///warn!("using default kafka seed, {}", kafka_seed);
+        //warn!("using default kafka seed, {}", kafka_seed);
         kafka_seed
     })
 }
 
-fn new_consumer(brokers: String, topics: &[String]) -> Result<StreamConsumer, KafkaError> {
-    let msg = topics.join(" ");
-    // info!("subscribing to topics {}", msg);
-    let stream_consumer: StreamConsumer = ClientConfig::new()
-        .set("group.id", "test-group")
-        .set("bootstrap.servers", &brokers)
-        .set("auto.offset.reset", "latest")
-        .set("enable.partition.eof", "true")
-        .set("session.timeout.ms", "6000")
-        .set("enable.auto.commit", "true")
-        .set_log_level(RDKafkaLogLevel::Debug)
-        .create()?;
-    let topics = topics
-        .iter()
-        .map(|topic| topic.as_str())
-        .collect::<Vec<&str>>();
-    stream_consumer.subscribe(topics.as_slice())?;
-    Ok(stream_consumer)
+/// Which event bus to fan hand events out with: `memory` for a single
+/// server, or `kafka` to share them between instances.
+pub fn event_bus_kind() -> String {
+    std::env::var("EVENT_BUS").unwrap_or_else(|_| "memory".to_string())
+}
+
+/// Where hands and tables are kept: `mongo`, or `memory` to run
+/// without a database.
+pub fn storage_kind() -> String {
+    std::env::var("STORAGE").unwrap_or_else(|_| "mongo".to_string())
+}
+
+/// The key user tokens are signed with. Without one, a key is made up at
+/// start, and tokens stop working when the server restarts.
+pub fn auth_key() -> String {
+    std::env::var("AUTH_KEY").unwrap_or_else(|_| {
+        println!("AUTH_KEY is not set, signing user tokens with a random key");
+        Uuid::new_v4().to_string()
+    })
+}
+
+/// Development accounts that can sign in with the login mutation, written
+/// as `user:password,user:password`.
+pub fn dev_accounts() -> Accounts {
+    Accounts::parse(&std::env::var("DEV_ACCOUNTS").unwrap_or_default())
+}
+
+/// How decks are shuffled: `seeded`, with a fresh seed kept on each hand,
+/// or `stacked` to deal the cards in `STACKED_DECK` every hand.
+pub fn shuffler_kind() -> String {
+    std::env::var("SHUFFLER").unwrap_or_else(|_| "seeded".to_string())
+}
+
+/// Seeds the seeded shuffler's hand seeds, to deal the same hands every
+/// run. Unset, they are random.
+pub fn shuffle_seed() -> Option<u64> {
+    std::env::var("SHUFFLE_SEED").ok().and_then(|seed| seed.parse().ok())
+}
+
+pub fn mongo_url() -> String {
+    std::env::var("MONGO_URL").unwrap_or_else(|_| "mongodb://localhost:27017".to_string())
 }
 
 pub async fn bootstrap_schema(
 ) -> Result<Schema<QueryRoot, MutationRoot, SubscriptionRoot>, Box<dyn std::error::Error>> {
-    // mongo
-    let mut client_options = ClientOptions::parse("mongodb://localhost:27017").await?;
-    let creds = Credential::builder()
-        .username("root".to_string())
-        .password("example".to_string())
-        .build();
-    client_options.credential = Some(creds.clone());
-    println!(
-        "Username: {}, Password: {}",
-        creds.username.unwrap(),
-        creds.password.unwrap()
-    );
-    let client = Client::with_options(client_options)?;
-    let db = client.database("poker");
-
-    //let topics = topics
-    //    .iter()
-    //    .map(|topic| topic.as_str())
-    //    .collect::<Vec<&str>>();
-    //stream_consumer.subscribe(topics.as_slice())?;
-    //
-    let topics = vec!["loony_topic".to_string()];
-    let kafka_host = kafka_seed();
-    let kafka_consumer: StreamConsumer = ClientConfig::new()
-        .set("group.id", "test-group")
-        .set("bootstrap.servers", &kafka_host)
-        .set("auto.offset.reset", "latest")
-        .set("enable.partition.eof", "false")
-        .set("session.timeout.ms", "6000")
-        .set("enable.auto.commit", "true")
-        .set_log_level(RDKafkaLogLevel::Debug)
-        .create()?;
-
-    let topics = topics
-        .iter()
-        .map(|topic| topic.as_str())
-        .collect::<Vec<&str>>();
-    kafka_consumer.subscribe(topics.as_slice())?;
-    let kafka_producer: FutureProducer = ClientConfig::new()
-        .set("bootstrap.servers", kafka_host)
-        .set("message.timeout.ms", "5000")
-        .create()
-        .expect("Producer creation error");
-
-    //let kafka_producer = ClientConfig::new()
-    //    .set("bootstrap.servers", kafka_host)
-    //    .set("produce.offset.report", "true")
-    //    .set("message.timeout.ms", "5000")
-    //    .create::<FutureProducer>();
+    let (hands, hand_log, tables): (
+        SharedHandRepository,
+        SharedHandLogRepository,
+        SharedTableRepository,
+    ) = match storage_kind().as_str() {
+        "memory" => (
+            Arc::new(InMemoryHandRepository::default()),
+            Arc::new(InMemoryHandLogRepository::default()),
+            Arc::new(InMemoryTableRepository::default()),
+        ),
+        _ => {
+            let mut client_options = ClientOptions::parse(mongo_url()).await?;
+            let creds = Credential::builder()
+                .username("root".to_string())
+                .password("example".to_string())
+                .build();
+            client_options.credential = Some(creds.clone());
+            println!(
+                "Username: {}, Password: {}",
+                creds.username.unwrap(),
+                creds.password.unwrap()
+            );
+            let client = Client::with_options(client_options)?;
+            let db = client.database("poker");
+            (
+                Arc::new(MongoHandRepository::new(&db)),
+                Arc::new(MongoHandLogRepository::new(&db).await?),
+                Arc::new(MongoTableRepository::new(&db)),
+            )
+        }
+    };
+
+    let event_bus: SharedEventBus = match event_bus_kind().as_str() {
+        "kafka" => {
+            // A consumer group per instance, so every server sees every event.
+            let group_id = format!("poker-{}", Uuid::new_v4());
+            Arc::new(KafkaEventBus::new(
+                &kafka_seed(),
+                "poker_events",
+                &group_id,
+            )?)
+        }
+        _ => Arc::new(InMemoryEventBus),
+    };
+
+    let shuffler: SharedShuffler = match shuffler_kind().as_str() {
+        "stacked" => {
+            let cards = std::env::var("STACKED_DECK").unwrap_or_default();
+            let cards: Vec<&str> = cards
+                .split(',')
+                .map(str::trim)
+                .filter(|card| !card.is_empty())
+                .collect();
+            Arc::new(StackedDeck::new(&cards)?)
+        }
+        _ => Arc::new(SeededShuffler::new(shuffle_seed())),
+    };
+
+    let scheduler: SharedScheduler = Arc::new(Scheduler::new(
+        hands.clone(),
+        hand_log.clone(),
+        tables.clone(),
+        event_bus.clone(),
+        shuffler,
+        Duration::from_secs(1),
+    ));
 
     Ok(Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
-        .data(db)
-        .data(kafka_consumer)
-        .data(kafka_producer)
+        .data(hands)
+        .data(hand_log)
+        .data(tables)
+        .data(event_bus)
+        .data(scheduler)
+        .data(tokens().clone())
+        .data(dev_accounts())
         .finish())
 }
 
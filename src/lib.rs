@@ -1,2 +1 @@
-pub mod bootstrap;
-use bootstrap::{bootstrap, bootstrap_schema};
\ No newline at end of file
+pub mod bootstrap;
\ No newline at end of file
@@ -1,4 +1,4 @@
-use actix_web::{web, App, HttpServer,Result,};
+use actix_web::{web, App, HttpServer};
 
 use unlimited_poker::bootstrap::{bootstrap, bootstrap_schema};
 
@@ -8,7 +8,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let schema = bootstrap_schema().await.unwrap();
 
-    let _ = HttpServer::new(move || App::new().app_data(web::Data::new(schema.clone())).configure(bootstrap))
+    HttpServer::new(move || App::new().app_data(web::Data::new(schema.clone())).configure(bootstrap))
         .bind("0.0.0.0:8097")?
         .run()
         .await?;
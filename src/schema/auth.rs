@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use async_graphql::{Context, ErrorExtensions, ID};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde_derive::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::UserToken;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long an issued token is accepted for, in seconds.
+pub const TOKEN_TTL: i64 = 24 * 60 * 60;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuthError {
+    Unauthenticated,
+    InvalidToken,
+    TokenExpired,
+    InvalidCredentials,
+}
+
+impl AuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::Unauthenticated => "UNAUTHENTICATED",
+            AuthError::InvalidToken => "INVALID_TOKEN",
+            AuthError::TokenExpired => "TOKEN_EXPIRED",
+            AuthError::InvalidCredentials => "INVALID_CREDENTIALS",
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Unauthenticated => {
+                write!(f, "Sign in and send the token as x-user-token")
+            }
+            AuthError::InvalidToken => write!(f, "User token was not issued by this server"),
+            AuthError::TokenExpired => write!(f, "User token has expired, sign in again"),
+            AuthError::InvalidCredentials => write!(f, "Unknown user or wrong password"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl ErrorExtensions for AuthError {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| e.set("code", self.code()))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Header {
+    alg: String,
+    typ: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Claims {
+    sub: String,
+    iat: i64,
+    exp: i64,
+}
+
+/// Issues and checks HS256 JSON web tokens naming the signed in user.
+#[derive(Clone)]
+pub struct TokenSigner {
+    key: Vec<u8>,
+}
+
+impl TokenSigner {
+    pub fn new(key: &[u8]) -> Self {
+        TokenSigner { key: key.to_vec() }
+    }
+
+    /// A token for `user_id`, valid for `TOKEN_TTL` seconds from `now`.
+    pub fn issue(&self, user_id: &str, now: i64) -> String {
+        let header = Header {
+            alg: "HS256".to_string(),
+            typ: "JWT".to_string(),
+        };
+        let claims = Claims {
+            sub: user_id.to_string(),
+            iat: now,
+            exp: now + TOKEN_TTL,
+        };
+        let signed = format!("{}.{}", encode(&header), encode(&claims));
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&signed).finalize().into_bytes());
+        format!("{}.{}", signed, signature)
+    }
+
+    /// The user a token was issued to, if it was signed with this key and
+    /// has not expired by `now`.
+    pub fn verify(&self, token: &str, now: i64) -> Result<ID, AuthError> {
+        let (signed, signature) = token.rsplit_once('.').ok_or(AuthError::InvalidToken)?;
+        let (header, claims) = signed.split_once('.').ok_or(AuthError::InvalidToken)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| AuthError::InvalidToken)?;
+        self.mac(signed)
+            .verify_slice(&signature)
+            .map_err(|_| AuthError::InvalidToken)?;
+
+        let header: Header = decode(header)?;
+        if header.alg != "HS256" {
+            return Err(AuthError::InvalidToken);
+        }
+        let claims: Claims = decode(claims)?;
+        if claims.exp <= now {
+            return Err(AuthError::TokenExpired);
+        }
+        Ok(claims.sub.into())
+    }
+
+    fn mac(&self, signed: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC takes keys of any size");
+        mac.update(signed.as_bytes());
+        mac
+    }
+}
+
+fn encode<T: serde::Serialize>(part: &T) -> String {
+    URL_SAFE_NO_PAD.encode(serde_json::to_vec(part).unwrap_or_default())
+}
+
+fn decode<T: serde::de::DeserializeOwned>(part: &str) -> Result<T, AuthError> {
+    let json = URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| AuthError::InvalidToken)?;
+    serde_json::from_slice(&json).map_err(|_| AuthError::InvalidToken)
+}
+
+/// Development accounts that may sign in, by user id and password.
+#[derive(Clone, Debug, Default)]
+pub struct Accounts(HashMap<String, String>);
+
+impl Accounts {
+    /// Reads accounts written as `user:password`, separated by commas.
+    pub fn parse(accounts: &str) -> Self {
+        Accounts(
+            accounts
+                .split(',')
+                .filter_map(|account| account.trim().split_once(':'))
+                .map(|(user_id, password)| (user_id.to_string(), password.to_string()))
+                .collect(),
+        )
+    }
+
+    pub fn check(&self, user_id: &str, password: &str) -> Result<(), AuthError> {
+        match self.0.get(user_id) {
+            Some(expected) if expected == password => Ok(()),
+            _ => Err(AuthError::InvalidCredentials),
+        }
+    }
+}
+
+/// The signed in user's id, verified from the `x-user-token` header.
+pub fn signed_in(ctx: &Context<'_>) -> async_graphql::Result<ID> {
+    ctx.data_opt::<UserToken>()
+        .map(|token| ID::from(token.0.as_str()))
+        .ok_or_else(|| AuthError::Unauthenticated.extend())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn issued_tokens_verify_until_they_expire() {
+        let signer = TokenSigner::new(b"secret");
+        let token = signer.issue("ann", 1_000);
+        assert_eq!(signer.verify(&token, 1_000), Ok("ann".into()));
+        assert_eq!(
+            signer.verify(&token, 1_000 + TOKEN_TTL),
+            Err(AuthError::TokenExpired)
+        );
+    }
+
+    #[test]
+    fn forged_tokens_are_rejected() {
+        let signer = TokenSigner::new(b"secret");
+        let token = signer.issue("ann", 1_000);
+        assert_eq!(
+            TokenSigner::new(b"other").verify(&token, 1_000),
+            Err(AuthError::InvalidToken)
+        );
+
+        // Swapping in someone else's claims breaks the signature.
+        let bob = signer.issue("bob", 1_000);
+        let parts: Vec<&str> = token.split('.').collect();
+        let forged = format!(
+            "{}.{}.{}",
+            parts[0],
+            bob.split('.').nth(1).unwrap(),
+            parts[2]
+        );
+        assert_eq!(signer.verify(&forged, 1_000), Err(AuthError::InvalidToken));
+
+        for token in ["", "ann", "a.b", "a.b.c"] {
+            assert_eq!(signer.verify(token, 1_000), Err(AuthError::InvalidToken));
+        }
+    }
+
+    #[test]
+    fn accounts_check_passwords() {
+        let accounts = Accounts::parse("ann:pw, bob:hunter2");
+        assert_eq!(accounts.check("bob", "hunter2"), Ok(()));
+        assert_eq!(
+            accounts.check("bob", "pw"),
+            Err(AuthError::InvalidCredentials)
+        );
+        assert_eq!(
+            accounts.check("cat", "pw"),
+            Err(AuthError::InvalidCredentials)
+        );
+    }
+}
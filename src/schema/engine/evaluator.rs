@@ -0,0 +1,301 @@
+use std::cmp::Reverse;
+
+const RANKS: &str = "23456789TJQKA";
+const SUITS: &str = "cdhs";
+
+/// Kinds of poker hand, weakest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Category {
+    HighCard,
+    Pair,
+    TwoPair,
+    ThreeOfAKind,
+    Straight,
+    Flush,
+    FullHouse,
+    FourOfAKind,
+    StraightFlush,
+}
+
+impl Category {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::HighCard => "High Card",
+            Category::Pair => "Pair",
+            Category::TwoPair => "Two Pair",
+            Category::ThreeOfAKind => "Three of a Kind",
+            Category::Straight => "Straight",
+            Category::Flush => "Flush",
+            Category::FullHouse => "Full House",
+            Category::FourOfAKind => "Four of a Kind",
+            Category::StraightFlush => "Straight Flush",
+        }
+    }
+}
+
+/// How strong a five card hand is. Hands compare by category, then by the
+/// ranks that decide between two hands of that category, most significant
+/// first: the pairs before the kickers, or a straight's top card.
+///
+/// Ranks run from 2 up to 14 for an ace, which counts as 1 in a five high
+/// straight.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HandRank {
+    pub category: Category,
+    pub ranks: Vec<u8>,
+}
+
+impl HandRank {
+    /// The rank as a number between 0 and 1 that orders hands the same way.
+    pub fn score(&self) -> f64 {
+        let mut ranks = self.ranks.clone();
+        ranks.resize(5, 0);
+        let value = ranks.iter().fold(self.category as u64, |value, rank| {
+            value * 15 + *rank as u64
+        });
+        value as f64 / (9 * 15u64.pow(5)) as f64
+    }
+
+    /// Such as "Two Pair, Kings and Sevens, Ace kicker".
+    pub fn description(&self) -> String {
+        let name = self.category.name();
+        let r = &self.ranks;
+        match self.category {
+            Category::HighCard => format!("{}, {}, {} kicker", name, rank(r[0]), rank(r[1])),
+            Category::Pair => format!("{}, {}, {} kicker", name, ranks(r[0]), rank(r[1])),
+            Category::TwoPair => format!(
+                "{}, {} and {}, {} kicker",
+                name,
+                ranks(r[0]),
+                ranks(r[1]),
+                rank(r[2])
+            ),
+            Category::ThreeOfAKind | Category::FourOfAKind => {
+                format!("{}, {}, {} kicker", name, ranks(r[0]), rank(r[1]))
+            }
+            Category::Straight | Category::Flush | Category::StraightFlush => {
+                format!("{}, {} high", name, rank(r[0]))
+            }
+            Category::FullHouse => format!("{}, {} full of {}", name, ranks(r[0]), ranks(r[1])),
+        }
+    }
+}
+
+fn rank(rank: u8) -> &'static str {
+    match rank {
+        2 => "Two",
+        3 => "Three",
+        4 => "Four",
+        5 => "Five",
+        6 => "Six",
+        7 => "Seven",
+        8 => "Eight",
+        9 => "Nine",
+        10 => "Ten",
+        11 => "Jack",
+        12 => "Queen",
+        13 => "King",
+        _ => "Ace",
+    }
+}
+
+fn ranks(rank: u8) -> &'static str {
+    match rank {
+        2 => "Twos",
+        3 => "Threes",
+        4 => "Fours",
+        5 => "Fives",
+        6 => "Sixes",
+        7 => "Sevens",
+        8 => "Eights",
+        9 => "Nines",
+        10 => "Tens",
+        11 => "Jacks",
+        12 => "Queens",
+        13 => "Kings",
+        _ => "Aces",
+    }
+}
+
+/// The best hand that can be made from some cards, and the five cards that
+/// make it, most significant first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evaluation {
+    pub rank: HandRank,
+    pub cards: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Card {
+    rank: u8,
+    suit: char,
+}
+
+fn parse(card: &str) -> Result<Card, String> {
+    let mut chars = card.chars();
+    let (Some(rank), Some(suit), None) = (chars.next(), chars.next(), chars.next()) else {
+        return Err(format!("{} is not a card", card));
+    };
+    match (RANKS.find(rank), SUITS.contains(suit)) {
+        (Some(i), true) => Ok(Card {
+            rank: i as u8 + 2,
+            suit,
+        }),
+        _ => Err(format!("{} is not a card", card)),
+    }
+}
+
+/// Ranks the best five of five to seven cards, such as a player's hole
+/// cards and the board.
+pub fn evaluate(cards: &[&str]) -> Result<Evaluation, String> {
+    if !(5..=7).contains(&cards.len()) {
+        return Err(format!("Can't rank {} cards, only 5 to 7", cards.len()));
+    }
+    let parsed = cards
+        .iter()
+        .map(|card| parse(card))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (i, card) in cards.iter().enumerate() {
+        if cards[..i].contains(card) {
+            return Err(format!("{} is there twice", card));
+        }
+    }
+
+    let (rank, cards) = (0u32..1 << parsed.len())
+        .filter(|chosen| chosen.count_ones() == 5)
+        .map(|chosen| {
+            let five = (0..parsed.len())
+                .filter(|i| chosen & 1 << i != 0)
+                .map(|i| parsed[i])
+                .collect();
+            rank_five(five)
+        })
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .expect("at least five cards");
+    Ok(Evaluation {
+        rank,
+        cards: cards
+            .iter()
+            .map(|card| {
+                format!(
+                    "{}{}",
+                    RANKS.as_bytes()[card.rank as usize - 2] as char,
+                    card.suit
+                )
+            })
+            .collect(),
+    })
+}
+
+/// Ranks exactly five cards, returning them most significant first.
+fn rank_five(mut cards: Vec<Card>) -> (HandRank, Vec<Card>) {
+    // Bigger groups first, then higher ranks, so pairs come before kickers.
+    let count = |rank: u8, cards: &[Card]| cards.iter().filter(|c| c.rank == rank).count();
+    let counted = cards.clone();
+    cards.sort_by_key(|c| (Reverse(count(c.rank, &counted)), Reverse(c.rank)));
+    let mut groups: Vec<(usize, u8)> = Vec::new();
+    for card in &cards {
+        if groups.last().map(|(_, rank)| *rank) != Some(card.rank) {
+            groups.push((count(card.rank, &cards), card.rank));
+        }
+    }
+
+    let flush = cards.iter().all(|c| c.suit == cards[0].suit);
+    let high = if groups.len() == 5 && cards[0].rank - cards[4].rank == 4 {
+        Some(cards[0].rank)
+    } else if groups.len() == 5 && cards[0].rank == 14 && cards[1].rank == 5 {
+        // A wheel: the ace plays low.
+        cards.rotate_left(1);
+        Some(5)
+    } else {
+        None
+    };
+
+    let category = match (high, flush, groups[0].0, groups.get(1).map(|g| g.0)) {
+        (Some(_), true, _, _) => Category::StraightFlush,
+        (_, _, 4, _) => Category::FourOfAKind,
+        (_, _, 3, Some(2)) => Category::FullHouse,
+        (_, true, _, _) => Category::Flush,
+        (Some(_), _, _, _) => Category::Straight,
+        (_, _, 3, _) => Category::ThreeOfAKind,
+        (_, _, 2, Some(2)) => Category::TwoPair,
+        (_, _, 2, _) => Category::Pair,
+        _ => Category::HighCard,
+    };
+    let ranks = match high {
+        Some(high) => vec![high],
+        None => groups.iter().map(|(_, rank)| *rank).collect(),
+    };
+    (HandRank { category, ranks }, cards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank_of(cards: &str) -> HandRank {
+        let cards: Vec<&str> = cards.split(' ').collect();
+        evaluate(&cards).unwrap().rank
+    }
+
+    #[test]
+    fn categories_rank_in_order() {
+        let hands = [
+            "Ah Kd 9c 7s 2h",
+            "2c 2d 9c 7s 3h",
+            "3c 3d 2h 2s Kh",
+            "4c 4d 4h 9s Kh",
+            "Ac 2d 3h 4s 5h",
+            "6c 2d 3h 4s 5h",
+            "2h 7h 9h Jh Kh",
+            "5c 5d 5h 9s 9h",
+            "6c 6d 6h 6s 2h",
+            "Ah 2h 3h 4h 5h",
+            "Th Jh Qh Kh Ah",
+        ];
+        let ranks: Vec<HandRank> = hands.iter().map(|hand| rank_of(hand)).collect();
+        for pair in ranks.windows(2) {
+            assert!(
+                pair[0] < pair[1],
+                "{:?} should lose to {:?}",
+                pair[0],
+                pair[1]
+            );
+            assert!(pair[0].score() < pair[1].score());
+        }
+        assert_eq!(ranks[4].ranks, [5]);
+    }
+
+    #[test]
+    fn kickers_break_ties() {
+        assert!(rank_of("Kc Kd 7h 7s Ah") > rank_of("Kc Kd 7h 7s Qh"));
+        assert!(rank_of("Kc Kd 8h 8s 2h") > rank_of("Kc Kd 7h 7s Ah"));
+        assert!(rank_of("9c 9d Ah Qs 3h") > rank_of("9h 9s Ad Js Th"));
+        assert_eq!(rank_of("9c 9d Ah Qs 3h"), rank_of("9h 9s Ad Qc 3s"));
+    }
+
+    #[test]
+    fn best_five_of_seven_are_chosen() {
+        let evaluation = evaluate(&["Kd", "7c", "Ks", "7d", "2c", "9h", "Ah"]).unwrap();
+        assert_eq!(evaluation.cards, ["Kd", "Ks", "7c", "7d", "Ah"]);
+        assert_eq!(
+            evaluation.rank.description(),
+            "Two Pair, Kings and Sevens, Ace kicker"
+        );
+
+        let wheel = evaluate(&["Ah", "Kd", "2c", "3s", "4d", "5h", "Kc"]).unwrap();
+        assert_eq!(wheel.cards, ["5h", "4d", "3s", "2c", "Ah"]);
+        assert_eq!(wheel.rank.description(), "Straight, Five high");
+        assert_eq!(
+            rank_of("5c 5d 5h 9s 9h Kc").description(),
+            "Full House, Fives full of Nines"
+        );
+    }
+
+    #[test]
+    fn bad_cards_are_rejected() {
+        assert!(evaluate(&["Ah", "Kd", "2c", "3s"]).is_err());
+        assert!(evaluate(&["Ah", "Kd", "2c", "3s", "1x"]).is_err());
+        assert!(evaluate(&["Ah", "Kd", "2c", "3s", "Ah"]).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt;
+
+use async_graphql::ID;
+
+use super::{start_hand, GameEvent, GameState, PlayerCommand, RuleError};
+use crate::bootstrap::schema::model::{
+    DealtEvent, Hand, HandLogEntry, HandLogEvent, PlayerAction, PlayerEvent, StreetDealtEvent,
+    StreetType,
+};
+
+/// Log events for a freshly dealt hand: the deal itself and its forced bets.
+pub fn dealt(hand: &Hand, straddle: bool) -> Vec<HandLogEvent> {
+    let mut events = vec![HandLogEvent::Dealt(Box::new(DealtEvent {
+        table_id: hand.table_id.clone(),
+        created_at: hand.created_at,
+        players: hand.players.clone(),
+        deck: hand.deck.clone(),
+        config: hand.config.clone(),
+        dealer_seat: hand.dealer_seat,
+        straddle,
+        action_deadline: hand.action_deadline,
+        seed: hand.seed.clone(),
+        seed_hash: hand.seed_hash.clone(),
+        client_seed: hand.client_seed.clone(),
+    }))];
+    events.extend(
+        hand.player_events
+            .iter()
+            .filter(|e| e.action == PlayerAction::Post)
+            .cloned()
+            .map(HandLogEvent::BlindPosted),
+    );
+    events
+}
+
+/// Log events for one accepted command, given what it caused and the hand
+/// afterwards.
+pub fn acted(game_events: &[GameEvent], hand: &Hand) -> Vec<HandLogEvent> {
+    let mut events = Vec::new();
+    let mut street_type = None;
+    for game_event in game_events {
+        match game_event {
+            GameEvent::PlayerActed(player_event) => {
+                street_type = Some(player_event.street_type);
+                events.push(HandLogEvent::PlayerActed(player_event.clone()));
+            }
+            GameEvent::StreetUpdated(street_event) => {
+                if street_type != Some(street_event.street_type) {
+                    let cards = match street_event.street_type {
+                        StreetType::Preflop => vec![],
+                        StreetType::Flop => hand.cards.flop.clone(),
+                        StreetType::Turn => vec![hand.cards.turn.clone()],
+                        StreetType::River => vec![hand.cards.river.clone()],
+                    };
+                    events.push(HandLogEvent::StreetDealt(StreetDealtEvent {
+                        street_type: street_event.street_type,
+                        cards,
+                        pot: street_event.pot,
+                    }));
+                }
+            }
+            GameEvent::HandCompleted(result) => {
+                let shown: Vec<_> = hand.players.iter().filter(|p| p.shown).cloned().collect();
+                if !shown.is_empty() {
+                    events.push(HandLogEvent::Showdown(shown));
+                }
+                events.extend(
+                    result
+                        .pot_awards
+                        .iter()
+                        .cloned()
+                        .map(HandLogEvent::PotAwarded),
+                );
+            }
+        }
+    }
+    events
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    NotDealt,
+    OutOfOrder { expected: i64, found: i64 },
+    Rule { sequence: i64, error: RuleError },
+    Diverged { sequence: i64 },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::NotDealt => write!(f, "Hand log does not start with a deal"),
+            ReplayError::OutOfOrder { expected, found } => {
+                write!(f, "Expected log entry {} but found {}", expected, found)
+            }
+            ReplayError::Rule { sequence, error } => {
+                write!(f, "Log entry {} was rejected: {}", sequence, error)
+            }
+            ReplayError::Diverged { sequence } => {
+                write!(f, "Replay differs from log entry {}", sequence)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Rebuilds a hand from its log by dealing it again and re-applying each
+/// action, checking everything the dealer logged along the way.
+pub fn replay(entries: &[HandLogEntry]) -> Result<Hand, ReplayError> {
+    for (i, entry) in entries.iter().enumerate() {
+        let expected = i as i64 + 1;
+        if entry.sequence != expected {
+            return Err(ReplayError::OutOfOrder {
+                expected,
+                found: entry.sequence,
+            });
+        }
+    }
+    let (first, rest) = entries.split_first().ok_or(ReplayError::NotDealt)?;
+    let dealt = match &first.event {
+        HandLogEvent::Dealt(dealt) => dealt,
+        _ => return Err(ReplayError::NotDealt),
+    };
+
+    let mut hand = start_hand(
+        first.hand_id.clone(),
+        dealt.table_id.clone(),
+        dealt.players.clone(),
+        dealt.deck.clone(),
+        dealt.config.clone(),
+        dealt.dealer_seat,
+        dealt.straddle,
+    );
+    hand.created_at = dealt.created_at;
+    hand.seed = dealt.seed.clone();
+    hand.seed_hash = dealt.seed_hash.clone();
+    hand.client_seed = dealt.client_seed.clone();
+    let mut state = GameState::new(hand);
+    let mut actions = 0;
+    let mut logged: Vec<(i64, &PlayerEvent)> = Vec::new();
+    let mut awards = Vec::new();
+
+    for entry in rest {
+        match &entry.event {
+            HandLogEvent::Dealt(_) => return Err(ReplayError::Diverged {
+                sequence: entry.sequence,
+            }),
+            HandLogEvent::BlindPosted(event) => logged.push((entry.sequence, event)),
+            HandLogEvent::PlayerActed(event) => {
+                logged.push((entry.sequence, event));
+                state
+                    .apply(PlayerCommand {
+                        player_id: event.player_id.clone(),
+                        action: event.action,
+                        amount: event.amount,
+                    })
+                    .map_err(|error| ReplayError::Rule {
+                        sequence: entry.sequence,
+                        error,
+                    })?;
+                actions += 1;
+            }
+            HandLogEvent::PotAwarded(award) => awards.push((entry.sequence, award)),
+            HandLogEvent::StreetDealt(_) | HandLogEvent::Showdown(_) => {}
+        }
+    }
+
+    let mut hand = state.into_hand();
+    hand.version = actions;
+    hand.log_sequence = entries.len() as i64;
+    // Later deadlines depend on when each action arrived, which isn't logged.
+    if actions == 0 {
+        hand.action_deadline = dealt.action_deadline;
+    }
+    for (i, (sequence, event)) in logged.iter().enumerate() {
+        if hand.player_events.get(i) != Some(*event) {
+            return Err(ReplayError::Diverged {
+                sequence: *sequence,
+            });
+        }
+    }
+    for (i, (sequence, award)) in awards.iter().enumerate() {
+        if hand.pot_awards.get(i) != Some(*award) {
+            return Err(ReplayError::Diverged {
+                sequence: *sequence,
+            });
+        }
+    }
+    Ok(hand)
+}
+
+/// Numbers log events to follow on from the hand's last entry, and claims
+/// their sequences on the hand. Save the hand before appending the entries,
+/// so two writers can't both number from the same entry.
+pub fn record(hand: &mut Hand, events: Vec<HandLogEvent>) -> Vec<HandLogEntry> {
+    let entries = number(&hand.id, hand.log_sequence, events);
+    hand.log_sequence += entries.len() as i64;
+    entries
+}
+
+/// Numbers log events to follow on from the entries already stored.
+pub fn number(hand_id: &ID, after: i64, events: Vec<HandLogEvent>) -> Vec<HandLogEntry> {
+    events
+        .into_iter()
+        .enumerate()
+        .map(|(i, event)| HandLogEntry {
+            hand_id: hand_id.clone(),
+            sequence: after + i as i64 + 1,
+            event,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::bootstrap::schema::model::{Player, TableConfig};
+
+    fn players() -> Vec<Player> {
+        [("sb", ["4s", "5h"]), ("bb", ["Ah", "Ad"]), ("btn", ["Qd", "Qc"])]
+            .iter()
+            .enumerate()
+            .map(|(i, (id, cards))| Player {
+                id: (*id).into(),
+                seat: i as i32,
+                stack: Decimal::new(1000, 0),
+                cards: cards.iter().map(|card| card.to_string()).collect(),
+                score: 0.0,
+                description: String::new(),
+                best_cards: vec![],
+                shown: false,
+                missed_small_blind: false,
+                missed_big_blind: false,
+            })
+            .collect()
+    }
+
+    fn deck() -> Vec<String> {
+        ["6s", "2c", "7d", "9h", "6h", "Js", "6d", "Kc"]
+            .iter()
+            .map(|card| card.to_string())
+            .collect()
+    }
+
+    fn command(id: &str, action: PlayerAction, amount: i64) -> PlayerCommand {
+        PlayerCommand {
+            player_id: id.into(),
+            action,
+            amount: Decimal::new(amount, 0),
+        }
+    }
+
+    /// Plays a hand to showdown, logging it the way the server does.
+    fn played() -> (Hand, Vec<HandLogEntry>) {
+        let hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            players(),
+            deck(),
+            TableConfig::default(),
+            2,
+            false,
+        );
+        let id = hand.id.clone();
+        let mut entries = number(&id, 0, dealt(&hand, false));
+        let mut state = GameState::new(hand);
+        let mut version = 0;
+        let commands = vec![
+            command("btn", PlayerAction::Raise, 60),
+            command("sb", PlayerAction::Call, 50),
+            command("bb", PlayerAction::Call, 40),
+            command("sb", PlayerAction::Check, 0),
+            command("bb", PlayerAction::Bet, 100),
+            command("btn", PlayerAction::Fold, 0),
+            command("sb", PlayerAction::Call, 100),
+            command("sb", PlayerAction::Check, 0),
+            command("bb", PlayerAction::Check, 0),
+            command("sb", PlayerAction::Check, 0),
+            command("bb", PlayerAction::Check, 0),
+        ];
+        for command in commands {
+            let events = state.apply(command).unwrap();
+            version += 1;
+            let after = entries.len() as i64;
+            entries.extend(number(&id, after, acted(&events, state.hand())));
+        }
+        let mut hand = state.into_hand();
+        hand.version = version;
+        hand.log_sequence = entries.len() as i64;
+        (hand, entries)
+    }
+
+    #[test]
+    fn log_records_each_step_of_the_hand() {
+        let (_, entries) = played();
+        let kinds: Vec<&str> = entries
+            .iter()
+            .map(|e| match e.event {
+                HandLogEvent::Dealt(_) => "dealt",
+                HandLogEvent::BlindPosted(_) => "blind",
+                HandLogEvent::PlayerActed(_) => "acted",
+                HandLogEvent::StreetDealt(_) => "street",
+                HandLogEvent::Showdown(_) => "showdown",
+                HandLogEvent::PotAwarded(_) => "award",
+            })
+            .collect();
+        assert_eq!(kinds[..3], ["dealt", "blind", "blind"]);
+        assert_eq!(kinds.iter().filter(|k| **k == "acted").count(), 11);
+        assert_eq!(kinds.iter().filter(|k| **k == "street").count(), 3);
+        assert_eq!(kinds[kinds.len() - 2..], ["showdown", "award"]);
+        let sequences: Vec<i64> = entries.iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, (1..=entries.len() as i64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn replay_rebuilds_the_hand() {
+        let (hand, entries) = played();
+        assert_eq!(replay(&entries), Ok(hand));
+    }
+
+    #[test]
+    fn replay_rejects_a_tampered_log() {
+        let (_, mut entries) = played();
+        if let HandLogEvent::PotAwarded(award) = &mut entries.last_mut().unwrap().event {
+            award.winner_ids = vec!["btn".into()];
+        }
+        let sequence = entries.len() as i64;
+        assert_eq!(replay(&entries), Err(ReplayError::Diverged { sequence }));
+
+        let (_, mut entries) = played();
+        entries.remove(4);
+        assert_eq!(
+            replay(&entries),
+            Err(ReplayError::OutOfOrder {
+                expected: 5,
+                found: 6
+            })
+        );
+        assert_eq!(replay(&[]), Err(ReplayError::NotDealt));
+    }
+}
@@ -0,0 +1,1644 @@
+use std::cmp::Reverse;
+use std::fmt;
+
+use async_graphql::{ErrorExtensions, ID};
+use rust_decimal::Decimal;
+
+use super::model::{
+    ActivePlayer, Cards, Hand, HandStatus, OddChipRule, Player, PlayerAction, PlayerEvent, PotAward,
+    StreetEvent, StreetType, TableConfig,
+};
+
+pub mod evaluator;
+pub mod log;
+pub mod pots;
+pub mod table;
+
+/// A single decision made by a player, as received from a client or a bot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerCommand {
+    pub player_id: ID,
+    pub action: PlayerAction,
+    pub amount: Decimal,
+}
+
+/// Everything that happened as the result of applying one command.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    PlayerActed(PlayerEvent),
+    StreetUpdated(StreetEvent),
+    HandCompleted(HandResult),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandResult {
+    pub pot_awards: Vec<PotAward>,
+    pub players: Vec<Player>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleError {
+    PlayerNotFound(ID),
+    NoStreet,
+    NoWinner,
+    PlayerFolded,
+    NoChips,
+    InvalidAmount { expected: Decimal },
+    InsufficientStack { stack: Decimal },
+    CannotCheck { to_call: Decimal },
+    NothingToCall,
+    BetFacingBet,
+    NothingToRaise,
+    BelowMinimum { minimum: Decimal },
+    BettingNotReopened,
+    InvalidAction,
+    Unauthorized,
+    OutOfTurn { to_act: Option<ID> },
+    HandComplete,
+    UnrankableHand { player_id: ID, reason: String },
+}
+
+impl RuleError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuleError::PlayerNotFound(_) => "PLAYER_NOT_FOUND",
+            RuleError::NoStreet => "NO_STREET",
+            RuleError::NoWinner => "NO_WINNER",
+            RuleError::PlayerFolded => "PLAYER_FOLDED",
+            RuleError::NoChips => "NO_CHIPS",
+            RuleError::InvalidAmount { .. } => "INVALID_AMOUNT",
+            RuleError::InsufficientStack { .. } => "INSUFFICIENT_STACK",
+            RuleError::CannotCheck { .. } => "CANNOT_CHECK",
+            RuleError::NothingToCall => "NOTHING_TO_CALL",
+            RuleError::BetFacingBet => "BET_FACING_BET",
+            RuleError::NothingToRaise => "NOTHING_TO_RAISE",
+            RuleError::BelowMinimum { .. } => "BELOW_MINIMUM",
+            RuleError::BettingNotReopened => "BETTING_NOT_REOPENED",
+            RuleError::InvalidAction => "INVALID_ACTION",
+            RuleError::Unauthorized => "UNAUTHORIZED",
+            RuleError::OutOfTurn { .. } => "OUT_OF_TURN",
+            RuleError::HandComplete => "HAND_COMPLETE",
+            RuleError::UnrankableHand { .. } => "UNRANKABLE_HAND",
+        }
+    }
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::PlayerNotFound(id) => write!(f, "Player {} not found", id.as_str()),
+            RuleError::NoStreet => write!(f, "Hand has no street in progress"),
+            RuleError::NoWinner => write!(f, "No winner found"),
+            RuleError::PlayerFolded => write!(f, "Player has already folded"),
+            RuleError::NoChips => write!(f, "Player has no chips left to act with"),
+            RuleError::InvalidAmount { expected } => {
+                write!(f, "Invalid amount for this action, expected {}", expected)
+            }
+            RuleError::InsufficientStack { stack } => {
+                write!(f, "Amount exceeds the player's stack of {}", stack)
+            }
+            RuleError::CannotCheck { to_call } => {
+                write!(f, "Cannot check when facing a bet, {} to call", to_call)
+            }
+            RuleError::NothingToCall => write!(f, "There is no bet to call"),
+            RuleError::BetFacingBet => write!(f, "Cannot bet when facing a bet, raise instead"),
+            RuleError::NothingToRaise => write!(f, "There is no bet to raise, bet instead"),
+            RuleError::BelowMinimum { minimum } => {
+                write!(f, "Amount is below the minimum of {}", minimum)
+            }
+            RuleError::BettingNotReopened => {
+                write!(
+                    f,
+                    "Betting was not reopened, the player may only call or fold"
+                )
+            }
+            RuleError::InvalidAction => write!(f, "Forced bets are posted by the dealer"),
+            RuleError::Unauthorized => write!(f, "Players may only act for their own seat"),
+            RuleError::OutOfTurn { .. } => write!(f, "It is not this player's turn to act"),
+            RuleError::HandComplete => write!(f, "Hand is already complete"),
+            RuleError::UnrankableHand { player_id, reason } => {
+                write!(f, "Can't rank {}'s hand: {}", player_id.as_str(), reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+impl ErrorExtensions for RuleError {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| {
+            e.set("code", self.code());
+            match self {
+                RuleError::InvalidAmount { expected } => e.set("expected", expected.to_string()),
+                RuleError::InsufficientStack { stack } => e.set("stack", stack.to_string()),
+                RuleError::CannotCheck { to_call } => e.set("toCall", to_call.to_string()),
+                RuleError::BelowMinimum { minimum } => e.set("minimum", minimum.to_string()),
+                RuleError::OutOfTurn {
+                    to_act: Some(to_act),
+                } => e.set("toAct", to_act.as_str()),
+                RuleError::UnrankableHand { player_id, .. } => {
+                    e.set("playerId", player_id.as_str())
+                }
+                _ => {}
+            }
+        })
+    }
+}
+
+/// Checks that the authenticated user is the player they are acting for.
+pub fn authorize(user_id: Option<&str>, player_id: &ID) -> Result<(), RuleError> {
+    match user_id {
+        Some(user_id) if user_id == player_id.as_str() => Ok(()),
+        _ => Err(RuleError::Unauthorized),
+    }
+}
+
+/// When the player to act at `now` runs out of time, before their time
+/// bank, or `None` once nobody is left to act.
+pub fn action_deadline(hand: &Hand, now: i64) -> Option<i64> {
+    hand.to_act
+        .as_ref()
+        .map(|_| now + hand.config.action_timeout as i64 * 1000)
+}
+
+/// Starts a hand from dealt players and the rest of the deck, posting the
+/// antes, blinds and any straddle the table's config calls for. The board is
+/// dealt from the top of `deck` as each street begins.
+///
+/// Seats are numbered clockwise. The small blind, big blind and straddle
+/// follow the button on `dealer_seat`, except heads-up where the button
+/// posts the small blind and acts first preflop.
+pub fn start_hand(
+    id: ID,
+    table_id: ID,
+    mut players: Vec<Player>,
+    deck: Vec<String>,
+    config: TableConfig,
+    dealer_seat: i32,
+    straddle: bool,
+) -> Hand {
+    players.sort_by_key(|p| p.seat);
+    let seats: Vec<i32> = players.iter().map(|p| p.seat).collect();
+    let heads_up = players.len() == 2;
+    let (small_blind, big_blind) = if heads_up { (1, 0) } else { (0, 1) };
+
+    let mut active_players: Vec<ActivePlayer> = clockwise_after(&seats, dealer_seat)
+        .into_iter()
+        .enumerate()
+        .map(|(position, i)| ActivePlayer {
+            id: players[i].id.clone(),
+            seat: players[i].seat,
+            bet: Decimal::ZERO,
+            stack: players[i].stack,
+            is_inactive: false,
+            is_big_blind: position == big_blind,
+            has_acted: false,
+        })
+        .collect();
+    let mut player_events = Vec::new();
+    let mut pot = Decimal::ZERO;
+
+    if config.ante > Decimal::ZERO {
+        for (position, player) in active_players.iter_mut().enumerate() {
+            if !config.big_blind_ante || position == big_blind {
+                post(player, config.ante, false, &mut pot, &mut player_events);
+            }
+        }
+    }
+    for (position, blind) in [(small_blind, config.small_blind), (big_blind, config.big_blind)] {
+        if let Some(player) = active_players.get_mut(position) {
+            post(player, blind, true, &mut pot, &mut player_events);
+        }
+    }
+    let straddle = straddle && config.straddle_allowed && active_players.len() > 2;
+    if straddle {
+        let amount = config.big_blind * Decimal::TWO;
+        post(
+            &mut active_players[2],
+            amount,
+            true,
+            &mut pot,
+            &mut player_events,
+        );
+    }
+
+    // Players back from sitting out post the blinds they missed, unless
+    // they are in the blinds this hand anyway.
+    let in_blinds = if straddle { 3 } else { 2 };
+    for player in active_players.iter_mut().skip(in_blinds) {
+        let Some(dealt) = players.iter().find(|p| p.id == player.id) else {
+            continue;
+        };
+        if dealt.missed_small_blind {
+            post(player, config.small_blind, false, &mut pot, &mut player_events);
+        }
+        if dealt.missed_big_blind {
+            post(player, config.big_blind, true, &mut pot, &mut player_events);
+        }
+    }
+
+    // Preflop the blinds, and a straddler, act last.
+    let last_forced = if straddle { 2 } else { big_blind };
+    let to_act = active_players
+        .get(last_forced)
+        .and_then(|p| next_to_act(&active_players, p.seat));
+
+    let min_raise = if straddle {
+        config.big_blind * Decimal::TWO
+    } else {
+        config.big_blind
+    };
+
+    Hand {
+        id,
+        table_id,
+        version: 0,
+        log_sequence: 0,
+        created_at: 0,
+        players,
+        cards: Cards::default(),
+        deck,
+        config,
+        pot_awards: vec![],
+        status: HandStatus::InProgress,
+        dealer_seat,
+        to_act: to_act.clone(),
+        last_aggressor: None,
+        action_deadline: None,
+        seed: None,
+        seed_hash: None,
+        client_seed: String::new(),
+        player_events,
+        street_events: vec![StreetEvent {
+            pot,
+            current_active_players: active_players,
+            street_type: StreetType::Preflop,
+            min_raise,
+            to_act,
+        }],
+    }
+}
+
+/// Takes a forced bet from a player, or whatever they have left. Live bets
+/// count towards what the player has to call; antes are dead money.
+fn post(
+    player: &mut ActivePlayer,
+    amount: Decimal,
+    live: bool,
+    pot: &mut Decimal,
+    player_events: &mut Vec<PlayerEvent>,
+) {
+    let amount = amount.min(player.stack);
+    player.stack -= amount;
+    if live {
+        player.bet += amount;
+    }
+    *pot += amount;
+    player_events.push(PlayerEvent {
+        player_id: player.id.clone(),
+        action: PlayerAction::Post,
+        amount,
+        street_type: StreetType::Preflop,
+        current_stack: player.stack,
+        current_pot: *pot,
+    });
+}
+
+/// The betting state machine for a single hand.
+///
+/// The state owns the `Hand` document and knows nothing about storage or
+/// transport, so the same rules run for GraphQL, bots and replays.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameState {
+    hand: Hand,
+}
+
+impl GameState {
+    pub fn new(hand: Hand) -> Self {
+        GameState { hand }
+    }
+
+    pub fn hand(&self) -> &Hand {
+        &self.hand
+    }
+
+    pub fn into_hand(self) -> Hand {
+        self.hand
+    }
+
+    /// What the player to act does when their clock runs out: check if they
+    /// can, fold otherwise.
+    pub fn timeout_command(&self) -> Option<PlayerCommand> {
+        let player_id = self.hand.to_act.clone()?;
+        let active_players = &self.hand.street_events.last()?.current_active_players;
+        let bet = active_players.iter().find(|p| p.id == player_id)?.bet;
+        let action = if bet < current_bet(active_players) {
+            PlayerAction::Fold
+        } else {
+            PlayerAction::Check
+        };
+        Some(PlayerCommand {
+            player_id,
+            action,
+            amount: Decimal::ZERO,
+        })
+    }
+
+    /// Plays the command, leaving the hand as it was if it is rejected.
+    pub fn apply(&mut self, command: PlayerCommand) -> Result<Vec<GameEvent>, RuleError> {
+        let mut next = GameState::new(self.hand.clone());
+        let events = next.play(command)?;
+        *self = next;
+        Ok(events)
+    }
+
+    fn play(&mut self, command: PlayerCommand) -> Result<Vec<GameEvent>, RuleError> {
+        if self.hand.status == HandStatus::Complete {
+            return Err(RuleError::HandComplete);
+        }
+        let street = self.hand.street_events.last().ok_or(RuleError::NoStreet)?;
+        let street_type = street.street_type;
+        let mut active_players = street.current_active_players.clone();
+        let mut min_raise = street.min_raise;
+        let pot = street.pot + command.amount;
+        let current_bet = current_bet(&active_players);
+
+        let actor = active_players
+            .iter()
+            .find(|p| p.id == command.player_id)
+            .ok_or_else(|| RuleError::PlayerNotFound(command.player_id.clone()))?;
+        if self.hand.to_act.as_ref() != Some(&command.player_id) {
+            return Err(RuleError::OutOfTurn {
+                to_act: self.hand.to_act.clone(),
+            });
+        }
+        let raise_by = validate(
+            actor,
+            &command,
+            current_bet,
+            min_raise,
+            self.hand.config.big_blind,
+        )?;
+
+        // An opening bet or a full raise reopens the betting for everyone
+        // else; a short all-in raise only obliges them to call or fold.
+        let reopens =
+            raise_by > Decimal::ZERO && (raise_by >= min_raise || current_bet == Decimal::ZERO);
+        if reopens {
+            min_raise = min_raise.max(raise_by);
+            for player in active_players.iter_mut() {
+                player.has_acted = false;
+            }
+        }
+
+        let actor = active_players
+            .iter_mut()
+            .find(|p| p.id == command.player_id)
+            .ok_or_else(|| RuleError::PlayerNotFound(command.player_id.clone()))?;
+        actor.bet += command.amount;
+        actor.stack -= command.amount;
+        actor.has_acted = true;
+        if command.action == PlayerAction::Fold {
+            actor.is_inactive = true;
+        }
+        let actor_seat = actor.seat;
+        if raise_by > Decimal::ZERO {
+            self.hand.last_aggressor = Some(command.player_id.clone());
+        }
+
+        let player_event = PlayerEvent {
+            player_id: command.player_id.clone(),
+            action: command.action,
+            amount: command.amount,
+            street_type,
+            current_stack: actor.stack,
+            current_pot: pot,
+        };
+        self.hand.player_events.push(player_event.clone());
+
+        let mut events = vec![GameEvent::PlayerActed(player_event)];
+
+        let remaining = active_players.iter().filter(|p| !p.is_inactive).count();
+        let should_change_street = is_round_complete(&active_players);
+        let can_still_bet = active_players.iter().filter(|p| can_act(p)).count() > 1;
+
+        let game_over = remaining == 1
+            || should_change_street && (street_type == StreetType::River || !can_still_bet);
+
+        if game_over {
+            self.hand.to_act = None;
+            self.hand.status = HandStatus::Complete;
+            // Players all in before the river see the rest of the board.
+            if remaining > 1 {
+                deal_street(&mut self.hand, StreetType::River);
+            }
+            let result = self.settle(&active_players)?;
+            events.push(GameEvent::HandCompleted(result));
+        } else {
+            let street_event = if should_change_street {
+                for player in active_players.iter_mut() {
+                    player.bet = Decimal::ZERO;
+                    player.has_acted = false;
+                }
+                // Postflop the first live player left of the button opens.
+                self.hand.last_aggressor = None;
+                let to_act = next_to_act(&active_players, self.hand.dealer_seat);
+                deal_street(&mut self.hand, next_street(street_type));
+                StreetEvent {
+                    pot,
+                    current_active_players: active_players,
+                    street_type: next_street(street_type),
+                    min_raise: self.hand.config.big_blind,
+                    to_act,
+                }
+            } else {
+                let to_act = next_to_act(&active_players, actor_seat);
+                StreetEvent {
+                    pot,
+                    current_active_players: active_players,
+                    street_type,
+                    min_raise,
+                    to_act,
+                }
+            };
+            self.hand.to_act = street_event.to_act.clone();
+            self.hand.street_events.push(street_event.clone());
+            events.push(GameEvent::StreetUpdated(street_event));
+        }
+
+        Ok(events)
+    }
+
+    /// Awards the main pot and each side pot to the best hands eligible for
+    /// it, chopping between exact ties.
+    fn settle(&mut self, active_players: &[ActivePlayer]) -> Result<HandResult, RuleError> {
+        let folded: Vec<ID> = active_players
+            .iter()
+            .filter(|p| p.is_inactive)
+            .map(|p| p.id.clone())
+            .collect();
+        let showdown = active_players.iter().filter(|p| !p.is_inactive).count() > 1;
+        // Without a showdown the one player left is all a pot is eligible to.
+        let ranks = if showdown {
+            self.rank_hands(&folded)?
+        } else {
+            vec![]
+        };
+        let rank = |id: &ID| {
+            ranks
+                .iter()
+                .find(|(ranked, _)| ranked == id)
+                .map(|(_, rank)| rank)
+        };
+
+        let mut pot_awards = Vec::new();
+        for pot in pots::build_pots(&self.hand.player_events, &folded) {
+            let best = pot
+                .eligible_player_ids
+                .iter()
+                .map(rank)
+                .max()
+                .ok_or(RuleError::NoWinner)?;
+            let winners: Vec<ID> = pot
+                .eligible_player_ids
+                .iter()
+                .filter(|id| rank(id) == best)
+                .cloned()
+                .collect();
+            let shares = pots::split_pot(
+                pot.amount,
+                &self.odd_chip_order(&winners),
+                self.hand.config.chip_denomination,
+            );
+            pot_awards.push(PotAward {
+                amount: pot.amount,
+                eligible_player_ids: pot.eligible_player_ids,
+                winner_ids: winners,
+                shares,
+            });
+        }
+
+        // Everyone still in at showdown turns their cards over; a player
+        // left alone wins without showing.
+        for player in self.hand.players.iter_mut() {
+            if let Some(active) = active_players.iter().find(|p| p.id == player.id) {
+                player.stack = active.stack;
+                player.shown = showdown && !active.is_inactive;
+            }
+            player.stack += pot_awards
+                .iter()
+                .flat_map(|award| award.shares.iter())
+                .filter(|share| share.player_id == player.id)
+                .map(|share| share.amount)
+                .sum::<Decimal>();
+        }
+        self.hand.pot_awards = pot_awards.clone();
+
+        Ok(HandResult {
+            pot_awards,
+            players: self.hand.players.clone(),
+        })
+    }
+
+    /// Ranks the hole cards of everyone still in against the board as it
+    /// was dealt, noting each player's best hand for display. A pot can't be
+    /// awarded fairly if any of them can't be ranked.
+    fn rank_hands(&mut self, folded: &[ID]) -> Result<Vec<(ID, evaluator::HandRank)>, RuleError> {
+        let board = &self.hand.cards;
+        let board: Vec<&str> = board
+            .flop
+            .iter()
+            .chain([&board.turn, &board.river])
+            .map(|card| card.as_str())
+            .filter(|card| !card.is_empty())
+            .collect();
+        let mut ranks = Vec::new();
+        for player in self.hand.players.iter_mut() {
+            if folded.contains(&player.id) {
+                continue;
+            }
+            if player.cards.len() != 2 {
+                return Err(RuleError::UnrankableHand {
+                    player_id: player.id.clone(),
+                    reason: format!("Dealt {} hole cards, not 2", player.cards.len()),
+                });
+            }
+            let cards: Vec<&str> = player
+                .cards
+                .iter()
+                .map(|card| card.as_str())
+                .chain(board.iter().copied())
+                .collect();
+            let evaluation =
+                evaluator::evaluate(&cards).map_err(|reason| RuleError::UnrankableHand {
+                    player_id: player.id.clone(),
+                    reason,
+                })?;
+            player.score = evaluation.rank.score();
+            player.description = evaluation.rank.description();
+            player.best_cards = evaluation.cards;
+            ranks.push((player.id.clone(), evaluation.rank));
+        }
+        Ok(ranks)
+    }
+
+    /// Player ids in seat order, starting with the first seat left of the button.
+    fn seats_from_button(&self) -> Vec<ID> {
+        let seats: Vec<i32> = self.hand.players.iter().map(|p| p.seat).collect();
+        clockwise_after(&seats, self.hand.dealer_seat)
+            .into_iter()
+            .map(|i| self.hand.players[i].id.clone())
+            .collect()
+    }
+
+    /// Orders tied winners by who receives odd chips first.
+    fn odd_chip_order(&self, winners: &[ID]) -> Vec<ID> {
+        match self.hand.config.odd_chip_rule {
+            OddChipRule::FirstLeftOfButton => self
+                .seats_from_button()
+                .into_iter()
+                .filter(|id| winners.contains(id))
+                .collect(),
+            OddChipRule::HighCardBySuit => {
+                let mut ordered = winners.to_vec();
+                ordered.sort_by_key(|id| {
+                    let high_card = self
+                        .hand
+                        .players
+                        .iter()
+                        .find(|p| p.id == *id)
+                        .and_then(|p| p.cards.iter().filter_map(|c| card_value(c)).max());
+                    Reverse(high_card)
+                });
+                ordered
+            }
+        }
+    }
+}
+
+/// Rank then suit of a card such as `"Td"`, suits ranked clubs, diamonds,
+/// hearts, spades from lowest to highest.
+fn card_value(card: &str) -> Option<(usize, usize)> {
+    let mut chars = card.chars();
+    let rank = "23456789TJQKA".find(chars.next()?)?;
+    let suit = "cdhs".find(chars.next()?)?;
+    Some((rank, suit))
+}
+
+/// Checks a command against the betting rules, returning how much it raises
+/// the current bet by.
+fn validate(
+    actor: &ActivePlayer,
+    command: &PlayerCommand,
+    current_bet: Decimal,
+    min_raise: Decimal,
+    big_blind: Decimal,
+) -> Result<Decimal, RuleError> {
+    if actor.is_inactive {
+        return Err(RuleError::PlayerFolded);
+    }
+    if actor.stack <= Decimal::ZERO {
+        return Err(RuleError::NoChips);
+    }
+    let amount = command.amount;
+    if amount < Decimal::ZERO {
+        return Err(RuleError::InvalidAmount {
+            expected: Decimal::ZERO,
+        });
+    }
+    if amount > actor.stack {
+        return Err(RuleError::InsufficientStack { stack: actor.stack });
+    }
+
+    let to_call = current_bet - actor.bet;
+    let raise_by = (actor.bet + amount - current_bet).max(Decimal::ZERO);
+    let is_all_in = amount == actor.stack;
+
+    match command.action {
+        PlayerAction::Post => return Err(RuleError::InvalidAction),
+        PlayerAction::Fold => {
+            if amount != Decimal::ZERO {
+                return Err(RuleError::InvalidAmount {
+                    expected: Decimal::ZERO,
+                });
+            }
+        }
+        PlayerAction::Check => {
+            if to_call > Decimal::ZERO {
+                return Err(RuleError::CannotCheck { to_call });
+            }
+            if amount != Decimal::ZERO {
+                return Err(RuleError::InvalidAmount {
+                    expected: Decimal::ZERO,
+                });
+            }
+        }
+        PlayerAction::Call => {
+            if to_call <= Decimal::ZERO {
+                return Err(RuleError::NothingToCall);
+            }
+            let expected = to_call.min(actor.stack);
+            if amount != expected {
+                return Err(RuleError::InvalidAmount { expected });
+            }
+        }
+        PlayerAction::Bet => {
+            if current_bet > Decimal::ZERO {
+                return Err(RuleError::BetFacingBet);
+            }
+            if amount < big_blind && !is_all_in {
+                return Err(RuleError::BelowMinimum { minimum: big_blind });
+            }
+        }
+        PlayerAction::Raise => {
+            if current_bet <= Decimal::ZERO {
+                return Err(RuleError::NothingToRaise);
+            }
+            if actor.has_acted {
+                return Err(RuleError::BettingNotReopened);
+            }
+            if raise_by < min_raise && !is_all_in {
+                return Err(RuleError::BelowMinimum {
+                    minimum: to_call + min_raise,
+                });
+            }
+        }
+        PlayerAction::AllIn => {
+            if !is_all_in {
+                return Err(RuleError::InvalidAmount {
+                    expected: actor.stack,
+                });
+            }
+            if raise_by > Decimal::ZERO && actor.has_acted {
+                return Err(RuleError::BettingNotReopened);
+            }
+        }
+    }
+
+    Ok(raise_by)
+}
+
+fn current_bet(active_players: &[ActivePlayer]) -> Decimal {
+    active_players
+        .iter()
+        .map(|p| p.bet)
+        .max()
+        .unwrap_or(Decimal::ZERO)
+}
+
+/// Whether a player still has decisions to make: not folded and not all-in.
+fn can_act(player: &ActivePlayer) -> bool {
+    !player.is_inactive && player.stack > Decimal::ZERO
+}
+
+/// Indices into `seats` going clockwise round the table, starting with the
+/// first seat after `seat`.
+fn clockwise_after(seats: &[i32], seat: i32) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..seats.len()).collect();
+    order.sort_by_key(|&i| (seats[i] <= seat, seats[i]));
+    order
+}
+
+/// The first player clockwise after `seat` who still owes a decision this
+/// round, skipping anyone folded or all-in.
+fn next_to_act(active_players: &[ActivePlayer], seat: i32) -> Option<ID> {
+    let current_bet = current_bet(active_players);
+    let seats: Vec<i32> = active_players.iter().map(|p| p.seat).collect();
+    clockwise_after(&seats, seat)
+        .into_iter()
+        .map(|i| &active_players[i])
+        .find(|p| can_act(p) && (!p.has_acted || p.bet < current_bet))
+        .map(|p| p.id.clone())
+}
+
+fn is_round_complete(active_players: &[ActivePlayer]) -> bool {
+    let current_bet = current_bet(active_players);
+    active_players
+        .iter()
+        .filter(|p| can_act(p))
+        .all(|p| p.has_acted && p.bet == current_bet)
+}
+
+/// Deals the community cards for `street_type` from the top of the hand's
+/// deck, along with those of any earlier street not dealt yet. A card is
+/// burned before each street.
+fn deal_street(hand: &mut Hand, street_type: StreetType) {
+    let (flop, turn, river) = match street_type {
+        StreetType::Preflop => (false, false, false),
+        StreetType::Flop => (true, false, false),
+        StreetType::Turn => (true, true, false),
+        StreetType::River => (true, true, true),
+    };
+    let deck = &mut hand.deck;
+    let mut draw = |n: usize| -> Vec<String> {
+        deck.drain(..(n + 1).min(deck.len())).skip(1).collect()
+    };
+    if flop && hand.cards.flop.is_empty() {
+        hand.cards.flop = draw(3);
+    }
+    if turn && hand.cards.turn.is_empty() {
+        hand.cards.turn = draw(1).concat();
+    }
+    if river && hand.cards.river.is_empty() {
+        hand.cards.river = draw(1).concat();
+    }
+}
+
+fn next_street(street_type: StreetType) -> StreetType {
+    match street_type {
+        StreetType::Preflop => StreetType::Flop,
+        StreetType::Flop => StreetType::Turn,
+        StreetType::Turn => StreetType::River,
+        StreetType::River => StreetType::Preflop,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(id: &str, cards: [&str; 2]) -> Player {
+        Player {
+            id: id.into(),
+            seat: 0,
+            stack: Decimal::new(1000, 0),
+            cards: cards.iter().map(|card| card.to_string()).collect(),
+            score: 0.0,
+            description: String::new(),
+            best_cards: vec![],
+            shown: false,
+            missed_small_blind: false,
+            missed_big_blind: false,
+        }
+    }
+
+    fn deck() -> Vec<String> {
+        ["6s", "2c", "7d", "9h", "6h", "Js", "6d", "Kc", "3d"]
+            .iter()
+            .map(|card| card.to_string())
+            .collect()
+    }
+
+    /// The board `deck()` runs out to, with a card burned before each street.
+    fn cards() -> Cards {
+        Cards {
+            flop: vec!["2c".into(), "7d".into(), "9h".into()],
+            turn: "Js".into(),
+            river: "Kc".into(),
+        }
+    }
+
+    /// Seats players in the order given, starting from seat 0.
+    fn seated(mut players: Vec<Player>) -> Vec<Player> {
+        for (i, player) in players.iter_mut().enumerate() {
+            player.seat = i as i32;
+        }
+        players
+    }
+
+    fn three_handed_players() -> Vec<Player> {
+        seated(vec![
+            player("sb", ["4s", "5h"]),
+            player("bb", ["Ah", "Ad"]),
+            player("btn", ["Qd", "Qc"]),
+        ])
+    }
+
+    fn three_handed() -> GameState {
+        let hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            three_handed_players(),
+            deck(),
+            TableConfig::default(),
+            2,
+            false,
+        );
+        GameState::new(hand)
+    }
+
+    fn set_stack(hand: &mut Hand, id: &str, stack: i64) {
+        for player in hand.street_events[0].current_active_players.iter_mut() {
+            if player.id == id {
+                player.stack = Decimal::new(stack, 0);
+            }
+        }
+    }
+
+    fn to_act(state: &GameState) -> Option<&str> {
+        state.hand().to_act.as_ref().map(|id| id.as_str())
+    }
+
+    fn command(id: &str, action: PlayerAction, amount: i64) -> PlayerCommand {
+        PlayerCommand {
+            player_id: id.into(),
+            action,
+            amount: Decimal::new(amount, 0),
+        }
+    }
+
+    fn street(state: &GameState) -> &StreetEvent {
+        state.hand().street_events.last().unwrap()
+    }
+
+    #[test]
+    fn start_hand_posts_blinds() {
+        let state = three_handed();
+        let street = street(&state);
+        assert_eq!(street.pot, Decimal::new(30, 0));
+        assert_eq!(street.min_raise, Decimal::new(20, 0));
+        assert_eq!(street.to_act, Some(ID::from("btn")));
+        assert_eq!(to_act(&state), Some("btn"));
+        assert_eq!(state.hand().dealer_seat, 2);
+        assert_eq!(state.hand().player_events.len(), 2);
+    }
+
+    #[test]
+    fn blinds_come_from_config() {
+        let config = TableConfig {
+            small_blind: Decimal::new(25, 0),
+            big_blind: Decimal::new(50, 0),
+            ante: Decimal::new(5, 0),
+            ..TableConfig::default()
+        };
+        let hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            three_handed_players(),
+            deck(),
+            config,
+            2,
+            false,
+        );
+        let street = hand.street_events.last().unwrap();
+        assert_eq!(street.pot, Decimal::new(90, 0));
+        assert_eq!(street.min_raise, Decimal::new(50, 0));
+        let bets: Vec<(&str, Decimal, bool)> = street
+            .current_active_players
+            .iter()
+            .map(|p| (p.id.as_str(), p.bet, p.is_big_blind))
+            .collect();
+        assert_eq!(
+            bets,
+            vec![
+                ("sb", Decimal::new(25, 0), false),
+                ("bb", Decimal::new(50, 0), true),
+                ("btn", Decimal::ZERO, false),
+            ]
+        );
+        assert_eq!(hand.player_events.len(), 5);
+        assert!(hand
+            .player_events
+            .iter()
+            .all(|e| e.action == PlayerAction::Post));
+    }
+
+    #[test]
+    fn big_blind_ante_is_posted_once() {
+        let config = TableConfig {
+            ante: Decimal::new(20, 0),
+            big_blind_ante: true,
+            ..TableConfig::default()
+        };
+        let hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            three_handed_players(),
+            deck(),
+            config,
+            2,
+            false,
+        );
+        let street = hand.street_events.last().unwrap();
+        assert_eq!(street.pot, Decimal::new(50, 0));
+        let bb = street
+            .current_active_players
+            .iter()
+            .find(|p| p.id == "bb")
+            .unwrap();
+        assert_eq!(bb.bet, Decimal::new(20, 0));
+        assert_eq!(bb.stack, Decimal::new(960, 0));
+    }
+
+    #[test]
+    fn heads_up_button_posts_small_blind_and_acts_first() {
+        let hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            seated(vec![player("btn", ["4s", "5h"]), player("bb", ["Ah", "Ad"])]),
+            deck(),
+            TableConfig::default(),
+            0,
+            false,
+        );
+        let street = hand.street_events.last().unwrap();
+        let btn = &street.current_active_players[1];
+        assert_eq!(btn.id, ID::from("btn"));
+        assert_eq!(btn.bet, Decimal::new(10, 0));
+        assert!(street.current_active_players[0].is_big_blind);
+        assert_eq!(street.to_act, Some(ID::from("btn")));
+    }
+
+    #[test]
+    fn heads_up_big_blind_acts_first_postflop() {
+        let hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            seated(vec![player("btn", ["4s", "5h"]), player("bb", ["Ah", "Ad"])]),
+            deck(),
+            TableConfig::default(),
+            0,
+            false,
+        );
+        let mut state = GameState::new(hand);
+        state.apply(command("btn", PlayerAction::Call, 10)).unwrap();
+        assert_eq!(to_act(&state), Some("bb"));
+        state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+        assert_eq!(street(&state).street_type, StreetType::Flop);
+        assert_eq!(to_act(&state), Some("bb"));
+        state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+        assert_eq!(to_act(&state), Some("btn"));
+    }
+
+    #[test]
+    fn blinds_follow_the_button_seat() {
+        let players = vec![
+            Player {
+                seat: 7,
+                ..player("a", ["4s", "5h"])
+            },
+            Player {
+                seat: 2,
+                ..player("b", ["Ah", "Ad"])
+            },
+            Player {
+                seat: 4,
+                ..player("c", ["Td", "Tc"])
+            },
+            Player {
+                seat: 9,
+                ..player("d", ["Qd", "Qc"])
+            },
+        ];
+        let hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            players,
+            deck(),
+            TableConfig::default(),
+            4,
+            false,
+        );
+        let street = &hand.street_events[0];
+        let order: Vec<(&str, Decimal)> = street
+            .current_active_players
+            .iter()
+            .map(|p| (p.id.as_str(), p.bet))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("a", Decimal::new(10, 0)),
+                ("d", Decimal::new(20, 0)),
+                ("b", Decimal::ZERO),
+                ("c", Decimal::ZERO),
+            ]
+        );
+        assert_eq!(street.to_act, Some(ID::from("b")));
+    }
+
+    #[test]
+    fn straddle_only_when_allowed() {
+        let players = vec![
+            player("sb", ["4s", "5h"]),
+            player("bb", ["Ah", "Ad"]),
+            player("utg", ["Td", "Tc"]),
+            player("btn", ["Qd", "Qc"]),
+        ];
+        let players = seated(players);
+        let hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            players.clone(),
+            deck(),
+            TableConfig::default(),
+            3,
+            true,
+        );
+        assert_eq!(hand.street_events[0].pot, Decimal::new(30, 0));
+
+        let config = TableConfig {
+            straddle_allowed: true,
+            ..TableConfig::default()
+        };
+        let hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            players,
+            deck(),
+            config,
+            3,
+            true,
+        );
+        let street = &hand.street_events[0];
+        assert_eq!(street.pot, Decimal::new(70, 0));
+        assert_eq!(street.min_raise, Decimal::new(40, 0));
+        let order: Vec<&str> = street
+            .current_active_players
+            .iter()
+            .map(|p| p.id.as_str())
+            .collect();
+        assert_eq!(order, vec!["sb", "bb", "utg", "btn"]);
+        assert_eq!(street.to_act, Some(ID::from("btn")));
+    }
+
+    #[test]
+    fn players_cannot_post() {
+        let mut state = three_handed();
+        let err = state
+            .apply(command("btn", PlayerAction::Post, 20))
+            .unwrap_err();
+        assert_eq!(err, RuleError::InvalidAction);
+    }
+
+    #[test]
+    fn apply_unknown_player_is_rejected() {
+        let mut state = three_handed();
+        let err = state
+            .apply(command("nobody", PlayerAction::Check, 0))
+            .unwrap_err();
+        assert_eq!(err, RuleError::PlayerNotFound("nobody".into()));
+    }
+
+    #[test]
+    fn last_player_standing_wins_pot() {
+        let mut state = three_handed();
+        state.apply(command("btn", PlayerAction::Fold, 0)).unwrap();
+        let events = state.apply(command("sb", PlayerAction::Fold, 0)).unwrap();
+        match events.last().unwrap() {
+            GameEvent::HandCompleted(result) => {
+                assert_eq!(result.pot_awards.len(), 1);
+                assert_eq!(result.pot_awards[0].winner_ids, vec![ID::from("bb")]);
+                assert_eq!(result.pot_awards[0].amount, Decimal::new(30, 0));
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        let bb = state.hand().players.iter().find(|p| p.id == "bb").unwrap();
+        assert_eq!(bb.stack, Decimal::new(1010, 0));
+    }
+
+    #[test]
+    fn returning_players_post_missed_blinds() {
+        let mut players = seated(vec![
+            player("sb", ["4s", "5h"]),
+            player("bb", ["Ah", "Ad"]),
+            player("utg", ["Td", "Tc"]),
+            player("btn", ["Qd", "Qc"]),
+        ]);
+        players[2].missed_small_blind = true;
+        players[2].missed_big_blind = true;
+        // The big blind owes nothing more than the blind it is posting.
+        players[1].missed_big_blind = true;
+        let state = GameState::new(start_hand(
+            "hand".into(),
+            "table".into(),
+            players,
+            deck(),
+            TableConfig::default(),
+            3,
+            false,
+        ));
+        let posts: Vec<(&str, Decimal)> = state
+            .hand()
+            .player_events
+            .iter()
+            .map(|e| (e.player_id.as_str(), e.amount))
+            .collect();
+        assert_eq!(
+            posts,
+            vec![
+                ("sb", Decimal::new(10, 0)),
+                ("bb", Decimal::new(20, 0)),
+                ("utg", Decimal::new(10, 0)),
+                ("utg", Decimal::new(20, 0)),
+            ]
+        );
+        let street = street(&state);
+        assert_eq!(street.pot, Decimal::new(60, 0));
+        let utg = street
+            .current_active_players
+            .iter()
+            .find(|p| p.id == "utg")
+            .unwrap();
+        // The dead small blind isn't part of the bet, and utg still acts.
+        assert_eq!(utg.bet, Decimal::new(20, 0));
+        assert_eq!(to_act(&state), Some("utg"));
+    }
+
+    #[test]
+    fn timeouts_check_when_possible_and_fold_otherwise() {
+        let mut state = three_handed();
+        assert_eq!(
+            state.timeout_command(),
+            Some(command("btn", PlayerAction::Fold, 0))
+        );
+        state.apply(command("btn", PlayerAction::Call, 20)).unwrap();
+        state.apply(command("sb", PlayerAction::Call, 10)).unwrap();
+        assert_eq!(
+            state.timeout_command(),
+            Some(command("bb", PlayerAction::Check, 0))
+        );
+    }
+
+    #[test]
+    fn postflop_action_skips_folded_and_all_in_players() {
+        let players = seated(vec![
+            player("sb", ["4s", "5h"]),
+            player("bb", ["Ah", "Ad"]),
+            player("utg", ["Td", "Tc"]),
+            player("btn", ["Qd", "Qc"]),
+        ]);
+        let mut hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            players,
+            deck(),
+            TableConfig::default(),
+            3,
+            false,
+        );
+        set_stack(&mut hand, "bb", 100);
+        let mut state = GameState::new(hand);
+        assert_eq!(to_act(&state), Some("utg"));
+        state.apply(command("utg", PlayerAction::Call, 20)).unwrap();
+        assert_eq!(to_act(&state), Some("btn"));
+        state.apply(command("btn", PlayerAction::Call, 20)).unwrap();
+        state.apply(command("sb", PlayerAction::Fold, 0)).unwrap();
+        state.apply(command("bb", PlayerAction::AllIn, 100)).unwrap();
+        assert_eq!(state.hand().last_aggressor, Some(ID::from("bb")));
+        assert_eq!(to_act(&state), Some("utg"));
+        state.apply(command("utg", PlayerAction::Call, 100)).unwrap();
+        state.apply(command("btn", PlayerAction::Call, 100)).unwrap();
+
+        assert_eq!(street(&state).street_type, StreetType::Flop);
+        assert_eq!(state.hand().last_aggressor, None);
+        assert_eq!(to_act(&state), Some("utg"));
+        state.apply(command("utg", PlayerAction::Bet, 50)).unwrap();
+        assert_eq!(to_act(&state), Some("btn"));
+        assert_eq!(state.hand().last_aggressor, Some(ID::from("utg")));
+    }
+
+    #[test]
+    fn check_facing_bet_is_rejected() {
+        let mut state = three_handed();
+        let err = state
+            .apply(command("btn", PlayerAction::Check, 0))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RuleError::CannotCheck {
+                to_call: Decimal::new(20, 0)
+            }
+        );
+    }
+
+    #[test]
+    fn check_with_amount_is_rejected() {
+        let mut state = three_handed();
+        state.apply(command("btn", PlayerAction::Call, 20)).unwrap();
+        state.apply(command("sb", PlayerAction::Call, 10)).unwrap();
+        let err = state
+            .apply(command("bb", PlayerAction::Check, 500))
+            .unwrap_err();
+        assert_eq!(err.code(), "INVALID_AMOUNT");
+    }
+
+    #[test]
+    fn call_must_match_amount_owed() {
+        let mut state = three_handed();
+        let err = state
+            .apply(command("btn", PlayerAction::Call, 15))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RuleError::InvalidAmount {
+                expected: Decimal::new(20, 0)
+            }
+        );
+    }
+
+    #[test]
+    fn raise_below_minimum_is_rejected() {
+        let mut state = three_handed();
+        let err = state
+            .apply(command("btn", PlayerAction::Raise, 30))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RuleError::BelowMinimum {
+                minimum: Decimal::new(40, 0)
+            }
+        );
+        state
+            .apply(command("btn", PlayerAction::Raise, 60))
+            .unwrap();
+        assert_eq!(street(&state).min_raise, Decimal::new(40, 0));
+    }
+
+    #[test]
+    fn bet_facing_bet_is_rejected() {
+        let mut state = three_handed();
+        let err = state
+            .apply(command("btn", PlayerAction::Bet, 40))
+            .unwrap_err();
+        assert_eq!(err, RuleError::BetFacingBet);
+    }
+
+    #[test]
+    fn short_all_in_does_not_reopen_betting() {
+        let mut hand = three_handed().into_hand();
+        set_stack(&mut hand, "sb", 15);
+        let mut state = GameState::new(hand);
+
+        state.apply(command("btn", PlayerAction::Call, 20)).unwrap();
+        // sb goes all-in for 25 total, a raise of 5 against a minimum of 20
+        state.apply(command("sb", PlayerAction::AllIn, 15)).unwrap();
+        state.apply(command("bb", PlayerAction::Call, 5)).unwrap();
+
+        let err = state
+            .apply(command("btn", PlayerAction::Raise, 100))
+            .unwrap_err();
+        assert_eq!(err, RuleError::BettingNotReopened);
+        state.apply(command("btn", PlayerAction::Call, 5)).unwrap();
+        assert_eq!(street(&state).street_type, StreetType::Flop);
+    }
+
+    #[test]
+    fn acting_with_zero_stack_is_rejected() {
+        let mut hand = three_handed().into_hand();
+        set_stack(&mut hand, "btn", 0);
+        let mut state = GameState::new(hand);
+        let err = state
+            .apply(command("btn", PlayerAction::Fold, 0))
+            .unwrap_err();
+        assert_eq!(err, RuleError::NoChips);
+    }
+
+    #[test]
+    fn all_in_for_less_only_wins_main_pot() {
+        let mut hand = three_handed().into_hand();
+        // sb has the worst hand, bb the best but only 90 behind
+        set_stack(&mut hand, "bb", 90);
+        let mut state = GameState::new(hand);
+
+        state
+            .apply(command("btn", PlayerAction::Raise, 300))
+            .unwrap();
+        state.apply(command("sb", PlayerAction::Call, 290)).unwrap();
+        state.apply(command("bb", PlayerAction::AllIn, 90)).unwrap();
+        let mut events = vec![];
+        for _ in 0..3 {
+            state.apply(command("sb", PlayerAction::Check, 0)).unwrap();
+            events = state.apply(command("btn", PlayerAction::Check, 0)).unwrap();
+        }
+
+        let result = match events.last().unwrap() {
+            GameEvent::HandCompleted(result) => result,
+            event => panic!("unexpected event {:?}", event),
+        };
+        let awards: Vec<(&str, Decimal)> = result
+            .pot_awards
+            .iter()
+            .map(|a| (a.winner_ids[0].as_str(), a.amount))
+            .collect();
+        assert_eq!(
+            awards,
+            vec![("bb", Decimal::new(330, 0)), ("btn", Decimal::new(380, 0))]
+        );
+        assert_eq!(state.hand().pot_awards.len(), 2);
+        let btn = state.hand().players.iter().find(|p| p.id == "btn").unwrap();
+        assert_eq!(btn.stack, Decimal::new(1080, 0));
+    }
+
+    fn check_down(state: &mut GameState, ids: &[&str]) {
+        while state.hand().pot_awards.is_empty() {
+            for id in ids {
+                state.apply(command(id, PlayerAction::Check, 0)).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn tied_hands_chop_the_pot() {
+        let mut hand = three_handed().into_hand();
+        // sb and bb both make aces with the same kickers off the board.
+        hand.players[0].cards = vec!["As".into(), "Ac".into()];
+        let mut state = GameState::new(hand);
+        state.apply(command("btn", PlayerAction::Fold, 0)).unwrap();
+        state.apply(command("sb", PlayerAction::Call, 10)).unwrap();
+        state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+        check_down(&mut state, &["sb", "bb"]);
+
+        let award = &state.hand().pot_awards[0];
+        assert_eq!(award.winner_ids, vec![ID::from("sb"), ID::from("bb")]);
+        let shares: Vec<Decimal> = award.shares.iter().map(|s| s.amount).collect();
+        assert_eq!(shares, vec![Decimal::new(20, 0), Decimal::new(20, 0)]);
+    }
+
+    #[test]
+    fn odd_chip_goes_left_of_button() {
+        let mut hand = three_handed().into_hand();
+        // bb and btn both make aces with the same kickers off the board.
+        hand.players[2].cards = vec!["As".into(), "Ac".into()];
+        hand.config.chip_denomination = Decimal::new(10, 0);
+        let mut state = GameState::new(hand);
+        state.apply(command("btn", PlayerAction::Call, 20)).unwrap();
+        state.apply(command("sb", PlayerAction::Call, 10)).unwrap();
+        state.apply(command("bb", PlayerAction::Raise, 25)).unwrap();
+        state.apply(command("btn", PlayerAction::Call, 25)).unwrap();
+        state.apply(command("sb", PlayerAction::Fold, 0)).unwrap();
+        check_down(&mut state, &["bb", "btn"]);
+
+        // 110 is eleven chips of 10, so bb, first left of the button, gets
+        // the odd one.
+        let shares: Vec<(&str, Decimal)> = state.hand().pot_awards[0]
+            .shares
+            .iter()
+            .map(|s| (s.player_id.as_str(), s.amount))
+            .collect();
+        assert_eq!(
+            shares,
+            vec![("bb", Decimal::new(60, 0)), ("btn", Decimal::new(50, 0))]
+        );
+        let stacks: Decimal = state.hand().players.iter().map(|p| p.stack).sum();
+        assert_eq!(stacks, Decimal::new(3000, 0));
+    }
+
+    #[test]
+    fn odd_chip_by_suit_goes_to_highest_card() {
+        let mut hand = three_handed().into_hand();
+        hand.config.odd_chip_rule = OddChipRule::HighCardBySuit;
+        // Both pair the king on the board with the same kickers.
+        hand.players[0].cards = vec!["Kd".into(), "4c".into()];
+        hand.players[1].cards = vec!["Ks".into(), "5c".into()];
+        let mut state = GameState::new(hand);
+        state.apply(command("btn", PlayerAction::Call, 20)).unwrap();
+        state.apply(command("sb", PlayerAction::Call, 10)).unwrap();
+        state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+        state.apply(command("sb", PlayerAction::Check, 0)).unwrap();
+        state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+        state.apply(command("btn", PlayerAction::Bet, 25)).unwrap();
+        state.apply(command("sb", PlayerAction::Call, 25)).unwrap();
+        state.apply(command("bb", PlayerAction::Call, 25)).unwrap();
+        state.apply(command("sb", PlayerAction::Check, 0)).unwrap();
+        state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+        state.apply(command("btn", PlayerAction::Fold, 0)).unwrap();
+        check_down(&mut state, &["sb", "bb"]);
+
+        let shares: Vec<(&str, Decimal)> = state.hand().pot_awards[0]
+            .shares
+            .iter()
+            .map(|s| (s.player_id.as_str(), s.amount))
+            .collect();
+        assert_eq!(
+            shares,
+            vec![("bb", Decimal::new(68, 0)), ("sb", Decimal::new(67, 0))]
+        );
+    }
+
+    #[test]
+    fn only_the_seated_user_may_act() {
+        assert_eq!(authorize(Some("btn"), &ID::from("btn")), Ok(()));
+        assert_eq!(
+            authorize(Some("sb"), &ID::from("btn")),
+            Err(RuleError::Unauthorized)
+        );
+        assert_eq!(
+            authorize(None, &ID::from("btn")),
+            Err(RuleError::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn acting_out_of_turn_is_rejected() {
+        let mut state = three_handed();
+        let err = state.apply(command("sb", PlayerAction::Fold, 0)).unwrap_err();
+        assert_eq!(
+            err,
+            RuleError::OutOfTurn {
+                to_act: Some("btn".into())
+            }
+        );
+        assert_eq!(err.code(), "OUT_OF_TURN");
+        assert_eq!(state.hand().player_events.len(), 2);
+
+        state.apply(command("btn", PlayerAction::Call, 20)).unwrap();
+        let err = state
+            .apply(command("btn", PlayerAction::Check, 0))
+            .unwrap_err();
+        assert_eq!(err.code(), "OUT_OF_TURN");
+    }
+
+    #[test]
+    fn acting_on_a_complete_hand_is_rejected() {
+        let mut state = three_handed();
+        state.apply(command("btn", PlayerAction::Fold, 0)).unwrap();
+        state.apply(command("sb", PlayerAction::Fold, 0)).unwrap();
+        assert_eq!(state.hand().status, HandStatus::Complete);
+        assert_eq!(state.hand().to_act, None);
+
+        let err = state
+            .apply(command("bb", PlayerAction::Check, 0))
+            .unwrap_err();
+        assert_eq!(err, RuleError::HandComplete);
+    }
+
+    #[test]
+    fn board_is_dealt_street_by_street() {
+        let mut state = three_handed();
+        assert_eq!(state.hand().cards, Cards::default());
+        state.apply(command("btn", PlayerAction::Call, 20)).unwrap();
+        state.apply(command("sb", PlayerAction::Call, 10)).unwrap();
+        state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+        let board = &state.hand().cards;
+        assert_eq!(board.flop, cards().flop);
+        assert_eq!(board.turn, "");
+        assert_eq!(board.river, "");
+        // 6s was burned before the flop, and 6h goes before the turn.
+        assert_eq!(state.hand().deck, ["6h", "Js", "6d", "Kc", "3d"]);
+
+        for id in ["sb", "bb", "btn"] {
+            state.apply(command(id, PlayerAction::Check, 0)).unwrap();
+        }
+        assert_eq!(state.hand().cards.turn, "Js");
+        assert_eq!(state.hand().cards.river, "");
+        assert_eq!(state.hand().deck, ["6d", "Kc", "3d"]);
+    }
+
+    #[test]
+    fn all_in_players_see_the_rest_of_the_board() {
+        let mut state = three_handed();
+        state.apply(command("btn", PlayerAction::AllIn, 1000)).unwrap();
+        state.apply(command("sb", PlayerAction::AllIn, 990)).unwrap();
+        state.apply(command("bb", PlayerAction::AllIn, 980)).unwrap();
+        assert_eq!(state.hand().status, HandStatus::Complete);
+        assert_eq!(state.hand().cards, cards());
+        assert_eq!(state.hand().deck, ["3d"]);
+    }
+
+    #[test]
+    fn only_showdown_hands_are_shown() {
+        let mut state = three_handed();
+        state.apply(command("btn", PlayerAction::Fold, 0)).unwrap();
+        state.apply(command("sb", PlayerAction::Call, 10)).unwrap();
+        state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+        check_down(&mut state, &["sb", "bb"]);
+
+        let shown: Vec<(&str, bool)> = state
+            .hand()
+            .players
+            .iter()
+            .map(|p| (p.id.as_str(), p.shown))
+            .collect();
+        assert_eq!(shown, vec![("sb", true), ("bb", true), ("btn", false)]);
+        let btn = &state.hand().players[2];
+        assert!(btn.is_visible_to(Some("btn")));
+        assert!(!btn.is_visible_to(Some("sb")));
+        assert!(!btn.is_visible_to(None));
+        assert_eq!(state.hand().cards, cards());
+    }
+
+    #[test]
+    fn showdown_ranks_hands_against_the_board() {
+        let mut players = three_handed_players();
+        players[0].cards = vec!["Kd".into(), "7c".into()];
+        players[1].cards = vec!["Qs".into(), "Qh".into()];
+        players[2].cards = vec!["Ac".into(), "Ad".into()];
+        let deck = ["6s", "Ks", "7d", "2c", "6h", "9h", "6d", "Ah"];
+        let hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            players,
+            deck.iter().map(|card| card.to_string()).collect(),
+            TableConfig::default(),
+            2,
+            false,
+        );
+        let mut state = GameState::new(hand);
+        state.apply(command("btn", PlayerAction::Fold, 0)).unwrap();
+        state.apply(command("sb", PlayerAction::Call, 10)).unwrap();
+        state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+        check_down(&mut state, &["sb", "bb"]);
+
+        // The big blind's queens lose to two pair made with the board.
+        let hand = state.hand();
+        assert_eq!(hand.pot_awards[0].winner_ids, vec![ID::from("sb")]);
+        assert_eq!(
+            hand.players[0].description,
+            "Two Pair, Kings and Sevens, Ace kicker"
+        );
+        assert_eq!(hand.players[0].best_cards, ["Kd", "Ks", "7c", "7d", "Ah"]);
+        assert_eq!(hand.players[1].description, "Pair, Queens, Ace kicker");
+        assert!(hand.players[2].best_cards.is_empty());
+    }
+
+    #[test]
+    fn unrankable_hands_stop_the_showdown() {
+        for (cards, reason) in [
+            (vec![], "Dealt 0 hole cards, not 2"),
+            (vec!["Ah".into(), "Kc".into()], "Kc is there twice"),
+        ] {
+            let mut hand = three_handed().into_hand();
+            hand.players[1].cards = cards;
+            let mut state = GameState::new(hand);
+            state.apply(command("btn", PlayerAction::Fold, 0)).unwrap();
+            state.apply(command("sb", PlayerAction::Call, 10)).unwrap();
+            state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+            for _ in 0..2 {
+                state.apply(command("sb", PlayerAction::Check, 0)).unwrap();
+                state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+            }
+            state.apply(command("sb", PlayerAction::Check, 0)).unwrap();
+            let before = state.hand().clone();
+
+            let err = state
+                .apply(command("bb", PlayerAction::Check, 0))
+                .unwrap_err();
+            assert_eq!(err.code(), "UNRANKABLE_HAND");
+            assert_eq!(
+                err,
+                RuleError::UnrankableHand {
+                    player_id: "bb".into(),
+                    reason: reason.into(),
+                }
+            );
+            // Nothing of the rejected check is kept.
+            assert_eq!(state.hand(), &before);
+        }
+    }
+
+    #[test]
+    fn winner_by_fold_does_not_show() {
+        let mut state = three_handed();
+        state.apply(command("btn", PlayerAction::Fold, 0)).unwrap();
+        state.apply(command("sb", PlayerAction::Fold, 0)).unwrap();
+        assert!(state.hand().players.iter().all(|p| !p.shown));
+        assert!(state.hand().cards.flop.is_empty());
+    }
+
+    #[test]
+    fn big_blind_gets_option_preflop() {
+        let mut state = three_handed();
+        state.apply(command("btn", PlayerAction::Call, 20)).unwrap();
+        state.apply(command("sb", PlayerAction::Call, 10)).unwrap();
+        assert_eq!(street(&state).street_type, StreetType::Preflop);
+        state.apply(command("bb", PlayerAction::Check, 0)).unwrap();
+        let street = street(&state);
+        assert_eq!(street.street_type, StreetType::Flop);
+        assert!(street
+            .current_active_players
+            .iter()
+            .all(|p| p.bet == Decimal::ZERO));
+    }
+}
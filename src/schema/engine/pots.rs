@@ -0,0 +1,261 @@
+use async_graphql::ID;
+use rust_decimal::Decimal;
+
+use crate::bootstrap::schema::model::{PlayerEvent, PotShare};
+
+/// A main or side pot and the players who can win it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pot {
+    pub amount: Decimal,
+    pub eligible_player_ids: Vec<ID>,
+}
+
+/// Total chips each player put in over the hand, in order of first action.
+pub fn contributions(player_events: &[PlayerEvent]) -> Vec<(ID, Decimal)> {
+    let mut totals: Vec<(ID, Decimal)> = Vec::new();
+    for event in player_events {
+        match totals.iter_mut().find(|(id, _)| *id == event.player_id) {
+            Some((_, total)) => *total += event.amount,
+            None => totals.push((event.player_id.clone(), event.amount)),
+        }
+    }
+    totals
+}
+
+/// Splits the hand's contributions into a main pot followed by side pots.
+///
+/// Each all-in level of a live player caps a pot; everyone who put in at
+/// least that much is eligible for it. Chips from folded players are dead
+/// money in whichever pots they reached.
+pub fn build_pots(player_events: &[PlayerEvent], folded: &[ID]) -> Vec<Pot> {
+    let totals = contributions(player_events);
+
+    let mut levels: Vec<Decimal> = totals
+        .iter()
+        .filter(|(id, total)| !folded.contains(id) && *total > Decimal::ZERO)
+        .map(|(_, total)| *total)
+        .collect();
+    levels.sort();
+    levels.dedup();
+
+    let mut pots: Vec<Pot> = Vec::new();
+    let mut previous = Decimal::ZERO;
+    for level in levels {
+        let amount: Decimal = totals
+            .iter()
+            .map(|(_, total)| (*total).min(level) - (*total).min(previous))
+            .sum();
+        let eligible_player_ids: Vec<ID> = totals
+            .iter()
+            .filter(|(id, total)| !folded.contains(id) && *total >= level)
+            .map(|(id, _)| id.clone())
+            .collect();
+        previous = level;
+
+        match pots.last_mut() {
+            Some(pot) if pot.eligible_player_ids == eligible_player_ids => pot.amount += amount,
+            _ => pots.push(Pot {
+                amount,
+                eligible_player_ids,
+            }),
+        }
+    }
+
+    // Folded players may have put in more than anyone still live.
+    let dead: Decimal = totals
+        .iter()
+        .map(|(_, total)| *total - (*total).min(previous))
+        .sum();
+    match pots.last_mut() {
+        Some(pot) => pot.amount += dead,
+        // No live player put anything in, so the dead money is all there is.
+        None if dead > Decimal::ZERO => pots.push(Pot {
+            amount: dead,
+            eligible_player_ids: totals
+                .iter()
+                .filter(|(id, _)| !folded.contains(id))
+                .map(|(id, _)| id.clone())
+                .collect(),
+        }),
+        None => {}
+    }
+
+    pots
+}
+
+/// Divides a pot between tied winners in whole chips of `denomination`.
+///
+/// Chips that don't divide evenly are handed out one at a time in the order
+/// the winners are given, along with any amount smaller than a single chip.
+pub fn split_pot(amount: Decimal, winners: &[ID], denomination: Decimal) -> Vec<PotShare> {
+    if winners.is_empty() {
+        return vec![];
+    }
+    let unit = if denomination > Decimal::ZERO {
+        denomination
+    } else {
+        Decimal::ONE
+    };
+    let chips = (amount / unit).floor();
+    let count = Decimal::from(winners.len());
+    let base = (chips / count).floor();
+    let mut odd_chips = chips - base * count;
+    let dust = amount - chips * unit;
+
+    winners
+        .iter()
+        .enumerate()
+        .map(|(i, player_id)| {
+            let mut share = base * unit;
+            if odd_chips > Decimal::ZERO {
+                share += unit;
+                odd_chips -= Decimal::ONE;
+            }
+            if i == 0 {
+                share += dust;
+            }
+            PotShare {
+                player_id: player_id.clone(),
+                amount: share,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::schema::model::{PlayerAction, StreetType};
+
+    fn event(id: &str, amount: i64) -> PlayerEvent {
+        PlayerEvent {
+            player_id: id.into(),
+            action: PlayerAction::Bet,
+            amount: Decimal::new(amount, 0),
+            street_type: StreetType::Preflop,
+            current_stack: Decimal::ZERO,
+            current_pot: Decimal::ZERO,
+        }
+    }
+
+    fn ids(ids: &[&str]) -> Vec<ID> {
+        ids.iter().map(|id| ID::from(*id)).collect()
+    }
+
+    #[test]
+    fn single_pot_when_everyone_matches() {
+        let events = vec![event("a", 100), event("b", 100), event("c", 100)];
+        let pots = build_pots(&events, &[]);
+        assert_eq!(
+            pots,
+            vec![Pot {
+                amount: Decimal::new(300, 0),
+                eligible_player_ids: ids(&["a", "b", "c"]),
+            }]
+        );
+    }
+
+    #[test]
+    fn short_all_in_creates_side_pots() {
+        let events = vec![
+            event("a", 50),
+            event("b", 200),
+            event("c", 200),
+            event("d", 120),
+        ];
+        let pots = build_pots(&events, &ids(&["d"]));
+        assert_eq!(
+            pots,
+            vec![
+                Pot {
+                    amount: Decimal::new(200, 0),
+                    eligible_player_ids: ids(&["a", "b", "c"]),
+                },
+                Pot {
+                    amount: Decimal::new(370, 0),
+                    eligible_player_ids: ids(&["b", "c"]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_way_all_in_orders_pots_by_level() {
+        let events = vec![
+            event("a", 30),
+            event("b", 80),
+            event("c", 150),
+            event("d", 150),
+        ];
+        let pots = build_pots(&events, &[]);
+        let amounts: Vec<Decimal> = pots.iter().map(|p| p.amount).collect();
+        assert_eq!(
+            amounts,
+            vec![
+                Decimal::new(120, 0),
+                Decimal::new(150, 0),
+                Decimal::new(140, 0)
+            ]
+        );
+        assert_eq!(pots[2].eligible_player_ids, ids(&["c", "d"]));
+    }
+
+    #[test]
+    fn folded_chips_above_live_levels_stay_in_last_pot() {
+        let events = vec![event("a", 40), event("b", 40), event("c", 100)];
+        let pots = build_pots(&events, &ids(&["c"]));
+        assert_eq!(pots.len(), 1);
+        assert_eq!(pots[0].amount, Decimal::new(180, 0));
+    }
+
+    #[test]
+    fn dead_money_alone_goes_to_the_live_players() {
+        let events = vec![event("a", 0), event("b", 0), event("c", 30)];
+        let pots = build_pots(&events, &ids(&["c"]));
+        assert_eq!(
+            pots,
+            vec![Pot {
+                amount: Decimal::new(30, 0),
+                eligible_player_ids: ids(&["a", "b"]),
+            }]
+        );
+    }
+
+    fn shares(shares: &[PotShare]) -> Vec<Decimal> {
+        shares.iter().map(|s| s.amount).collect()
+    }
+
+    #[test]
+    fn split_pot_evenly() {
+        let split = split_pot(Decimal::new(300, 0), &ids(&["a", "b", "c"]), Decimal::ONE);
+        assert_eq!(shares(&split), vec![Decimal::new(100, 0); 3]);
+    }
+
+    #[test]
+    fn split_pot_hands_out_odd_chips_in_order() {
+        let split = split_pot(Decimal::new(100, 0), &ids(&["a", "b", "c"]), Decimal::ONE);
+        assert_eq!(
+            shares(&split),
+            vec![
+                Decimal::new(34, 0),
+                Decimal::new(33, 0),
+                Decimal::new(33, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn split_pot_respects_denomination() {
+        let split = split_pot(Decimal::new(2550, 2), &ids(&["a", "b"]), Decimal::new(5, 1));
+        assert_eq!(
+            shares(&split),
+            vec![Decimal::new(1300, 2), Decimal::new(1250, 2)]
+        );
+
+        let split = split_pot(Decimal::new(27, 0), &ids(&["a", "b"]), Decimal::new(5, 0));
+        assert_eq!(
+            shares(&split),
+            vec![Decimal::new(17, 0), Decimal::new(10, 0)]
+        );
+    }
+}
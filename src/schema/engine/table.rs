@@ -0,0 +1,690 @@
+use std::fmt;
+
+use async_graphql::{ErrorExtensions, ID};
+use rust_decimal::Decimal;
+
+use super::clockwise_after;
+use crate::bootstrap::schema::model::{Hand, HandStatus, Seat, Table, TableStatus};
+
+/// The most seats a table can have.
+pub const MAX_SEATS: i32 = 10;
+
+/// The longest client seed a player may contribute to the shuffle.
+pub const MAX_CLIENT_SEED_LEN: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TableError {
+    NotOwner,
+    InvalidMaxSeats,
+    Closed,
+    InvalidSeat { max_seats: i32 },
+    SeatTaken { seat: i32 },
+    AlreadySeated { seat: i32 },
+    BuyInOutOfRange { min: Decimal, max: Decimal },
+    NotSeated,
+    InHand,
+    HandInProgress { hand_id: ID },
+    NotEnoughPlayers,
+    TooManyPlayers { max_seats: i32 },
+    InvalidClientSeed,
+}
+
+impl TableError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TableError::NotOwner => "NOT_TABLE_OWNER",
+            TableError::InvalidMaxSeats => "INVALID_MAX_SEATS",
+            TableError::Closed => "TABLE_CLOSED",
+            TableError::InvalidSeat { .. } => "INVALID_SEAT",
+            TableError::SeatTaken { .. } => "SEAT_TAKEN",
+            TableError::AlreadySeated { .. } => "ALREADY_SEATED",
+            TableError::BuyInOutOfRange { .. } => "BUY_IN_OUT_OF_RANGE",
+            TableError::NotSeated => "NOT_SEATED",
+            TableError::InHand => "IN_HAND",
+            TableError::HandInProgress { .. } => "HAND_IN_PROGRESS",
+            TableError::NotEnoughPlayers => "NOT_ENOUGH_PLAYERS",
+            TableError::TooManyPlayers { .. } => "TOO_MANY_PLAYERS",
+            TableError::InvalidClientSeed => "INVALID_CLIENT_SEED",
+        }
+    }
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TableError::NotOwner => write!(f, "Only the table's owner may change it"),
+            TableError::InvalidMaxSeats => {
+                write!(f, "Tables have between 2 and {} seats", MAX_SEATS)
+            }
+            TableError::Closed => write!(f, "Table is closed"),
+            TableError::InvalidSeat { max_seats } => {
+                write!(f, "Seats are numbered from 0 to {}", max_seats - 1)
+            }
+            TableError::SeatTaken { seat } => write!(f, "Seat {} is taken", seat),
+            TableError::AlreadySeated { seat } => {
+                write!(f, "Player is already sitting in seat {}", seat)
+            }
+            TableError::BuyInOutOfRange { min, max } => {
+                write!(f, "Buy-in must be between {} and {}", min, max)
+            }
+            TableError::NotSeated => write!(f, "Player is not seated at this table"),
+            TableError::InHand => write!(f, "Players cannot leave during a hand they were dealt"),
+            TableError::HandInProgress { hand_id } => {
+                write!(f, "Hand {} is still being played", hand_id.as_str())
+            }
+            TableError::NotEnoughPlayers => {
+                write!(
+                    f,
+                    "At least two seated players with chips are needed to deal"
+                )
+            }
+            TableError::TooManyPlayers { max_seats } => {
+                write!(f, "At most {} players can be dealt in", max_seats)
+            }
+            TableError::InvalidClientSeed => {
+                write!(
+                    f,
+                    "Client seeds are 1 to {} characters",
+                    MAX_CLIENT_SEED_LEN
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TableError {}
+
+impl ErrorExtensions for TableError {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| {
+            e.set("code", self.code());
+            match self {
+                TableError::InvalidSeat { max_seats }
+                | TableError::TooManyPlayers { max_seats } => e.set("maxSeats", *max_seats),
+                TableError::BuyInOutOfRange { min, max } => {
+                    e.set("min", min.to_string());
+                    e.set("max", max.to_string());
+                }
+                TableError::HandInProgress { hand_id } => e.set("handId", hand_id.as_str()),
+                _ => {}
+            }
+        })
+    }
+}
+
+pub fn validate_max_seats(max_seats: i32) -> Result<(), TableError> {
+    if (2..=MAX_SEATS).contains(&max_seats) {
+        Ok(())
+    } else {
+        Err(TableError::InvalidMaxSeats)
+    }
+}
+
+/// Checks that the user owns the table before they configure or close it.
+pub fn authorize_owner(table: &Table, user_id: &ID) -> Result<(), TableError> {
+    if table.owner_id == *user_id {
+        Ok(())
+    } else {
+        Err(TableError::NotOwner)
+    }
+}
+
+/// Sits a player down with `buy_in` chips. They are dealt in from the next
+/// hand.
+pub fn join(
+    table: &mut Table,
+    player_id: &ID,
+    seat: i32,
+    buy_in: Decimal,
+) -> Result<(), TableError> {
+    if table.status == TableStatus::Closed {
+        return Err(TableError::Closed);
+    }
+    if !(0..table.max_seats).contains(&seat) {
+        return Err(TableError::InvalidSeat {
+            max_seats: table.max_seats,
+        });
+    }
+    if let Some(sitting) = table.seats.iter().find(|s| s.player_id == *player_id) {
+        return Err(TableError::AlreadySeated { seat: sitting.seat });
+    }
+    if table.seats.iter().any(|s| s.seat == seat) {
+        return Err(TableError::SeatTaken { seat });
+    }
+    let (min, max) = (table.config.min_buy_in, table.config.max_buy_in);
+    if buy_in < min || buy_in > max {
+        return Err(TableError::BuyInOutOfRange { min, max });
+    }
+    table.seats.push(Seat {
+        seat,
+        player_id: player_id.clone(),
+        stack: buy_in,
+        sitting_out: false,
+        time_bank: table.config.time_bank,
+        timeouts: 0,
+        missed_small_blind: false,
+        missed_big_blind: false,
+        wait_for_big_blind: false,
+        client_seed: None,
+    });
+    table.seats.sort_by_key(|s| s.seat);
+    Ok(())
+}
+
+/// Stands a player up, returning their seat with the chips they leave with.
+/// `current_hand` is the table's hand, if one is still being played.
+pub fn leave(
+    table: &mut Table,
+    player_id: &ID,
+    current_hand: Option<&Hand>,
+) -> Result<Seat, TableError> {
+    let index = table
+        .seats
+        .iter()
+        .position(|s| s.player_id == *player_id)
+        .ok_or(TableError::NotSeated)?;
+    if current_hand.is_some_and(|hand| hand.players.iter().any(|p| p.id == *player_id)) {
+        return Err(TableError::InHand);
+    }
+    Ok(table.seats.remove(index))
+}
+
+/// Stops the table dealing. A hand already being played is finished.
+pub fn close(table: &mut Table) {
+    table.status = TableStatus::Closed;
+}
+
+/// Stops dealing the player in from the next hand. They keep their seat.
+pub fn sit_out(table: &mut Table, player_id: &ID) -> Result<Seat, TableError> {
+    let seat = seat_mut(table, player_id)?;
+    seat.sitting_out = true;
+    seat.wait_for_big_blind = false;
+    Ok(seat.clone())
+}
+
+/// Deals the player back in. Blinds they missed are posted with their next
+/// hand, unless they wait for the big blind to reach them instead.
+pub fn sit_in(
+    table: &mut Table,
+    player_id: &ID,
+    wait_for_big_blind: bool,
+) -> Result<Seat, TableError> {
+    if table.status == TableStatus::Closed {
+        return Err(TableError::Closed);
+    }
+    let seat = seat_mut(table, player_id)?;
+    seat.sitting_out = false;
+    seat.timeouts = 0;
+    seat.wait_for_big_blind =
+        wait_for_big_blind && (seat.missed_small_blind || seat.missed_big_blind);
+    Ok(seat.clone())
+}
+
+/// Sets the seed the player mixes into the shuffle of the hands they're
+/// dealt, from the next hand.
+pub fn set_client_seed(
+    table: &mut Table,
+    player_id: &ID,
+    client_seed: &str,
+) -> Result<Seat, TableError> {
+    if client_seed.is_empty() || client_seed.len() > MAX_CLIENT_SEED_LEN {
+        return Err(TableError::InvalidClientSeed);
+    }
+    let seat = seat_mut(table, player_id)?;
+    seat.client_seed = Some(client_seed.to_string());
+    Ok(seat.clone())
+}
+
+/// The client seed for a hand dealt to `seats`: each player's seed in seat
+/// order.
+pub fn client_seed(seats: &[Seat]) -> String {
+    seats
+        .iter()
+        .filter_map(|s| s.client_seed.as_deref())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn seat_mut<'a>(table: &'a mut Table, player_id: &ID) -> Result<&'a mut Seat, TableError> {
+    table
+        .seats
+        .iter_mut()
+        .find(|s| s.player_id == *player_id)
+        .ok_or(TableError::NotSeated)
+}
+
+/// The seats to deal the next hand to, and the seat the button moves to.
+/// `current_hand` is the table's hand, if one is still being played.
+///
+/// Players waiting for the big blind are dealt in once it reaches them, or
+/// sooner if there is no one else to play.
+pub fn deal_in(table: &Table, current_hand: Option<&Hand>) -> Result<(Vec<Seat>, i32), TableError> {
+    if table.status == TableStatus::Closed {
+        return Err(TableError::Closed);
+    }
+    if let Some(hand) = current_hand {
+        return Err(TableError::HandInProgress {
+            hand_id: hand.id.clone(),
+        });
+    }
+    let ready: Vec<Seat> = table
+        .seats
+        .iter()
+        .filter(|s| !s.sitting_out && s.stack > Decimal::ZERO)
+        .cloned()
+        .collect();
+    if ready.len() < 2 {
+        return Err(TableError::NotEnoughPlayers);
+    }
+    if ready.len() > table.max_seats as usize {
+        return Err(TableError::TooManyPlayers {
+            max_seats: table.max_seats,
+        });
+    }
+    let numbers: Vec<i32> = ready.iter().map(|s| s.seat).collect();
+    let dealer_seat = match table.dealer_seat {
+        Some(last) => numbers[clockwise_after(&numbers, last)[0]],
+        None => numbers[0],
+    };
+
+    let (_, big_blind) = blind_seats(&numbers, dealer_seat);
+    let mut seats: Vec<Seat> = ready
+        .iter()
+        .filter(|s| !s.wait_for_big_blind || s.seat == big_blind)
+        .cloned()
+        .collect();
+    if seats.len() < 2 {
+        seats = ready;
+    }
+    for seat in seats.iter_mut() {
+        // Posting the big blind covers whatever they missed.
+        if seat.wait_for_big_blind && seat.seat == big_blind {
+            seat.missed_small_blind = false;
+            seat.missed_big_blind = false;
+        }
+    }
+    Ok((seats, dealer_seat))
+}
+
+/// The small and big blind seats for players on `seats` with the button on
+/// `dealer_seat`. Heads-up the button is the small blind.
+fn blind_seats(seats: &[i32], dealer_seat: i32) -> (i32, i32) {
+    let order: Vec<i32> = clockwise_after(seats, dealer_seat)
+        .into_iter()
+        .map(|i| seats[i])
+        .collect();
+    if seats.len() == 2 {
+        (order[1], order[0])
+    } else {
+        (order[0], order[1])
+    }
+}
+
+/// Whether `seat` comes strictly after `from` and before `to`, going
+/// clockwise.
+fn clockwise_between(from: i32, seat: i32, to: i32) -> bool {
+    let key = |s: i32| (s <= from, s);
+    seat != from && key(seat) < key(to)
+}
+
+/// Records that the table dealt `hand`. Players it was dealt to have paid
+/// any blinds they owed, and players sitting it out are charged with the
+/// blinds that passed their seat.
+pub fn start(table: &mut Table, hand: &Hand) {
+    table.current_hand_id = Some(hand.id.clone());
+    table.dealer_seat = Some(hand.dealer_seat);
+
+    let mut dealt: Vec<i32> = hand.players.iter().map(|p| p.seat).collect();
+    dealt.sort();
+    let (small_blind, big_blind) = blind_seats(&dealt, hand.dealer_seat);
+    for seat in table.seats.iter_mut() {
+        if hand.players.iter().any(|p| p.id == seat.player_id) {
+            seat.missed_small_blind = false;
+            seat.missed_big_blind = false;
+            seat.wait_for_big_blind = false;
+        } else if !seat.sitting_out && !seat.wait_for_big_blind {
+            continue;
+        } else if small_blind != hand.dealer_seat
+            && clockwise_between(hand.dealer_seat, seat.seat, small_blind)
+        {
+            // Heads-up the button posts the small blind, so it passes no one.
+            seat.missed_small_blind = true;
+        } else if clockwise_between(small_blind, seat.seat, big_blind) {
+            seat.missed_big_blind = true;
+        }
+    }
+}
+
+/// Seconds of time bank the player has left.
+pub fn time_bank(table: &Table, player_id: &ID) -> i32 {
+    table
+        .seats
+        .iter()
+        .find(|s| s.player_id == *player_id)
+        .map_or(0, |s| s.time_bank)
+}
+
+/// Charges a player's time bank for acting `overtime` milliseconds past
+/// their deadline, and counts the timeouts they've had in a row, sitting
+/// them out once there are too many.
+pub fn record_action(table: &mut Table, player_id: &ID, overtime: i64, timed_out: bool) {
+    let max_timeouts = table.config.max_timeouts;
+    if let Some(seat) = table.seats.iter_mut().find(|s| s.player_id == *player_id) {
+        let used = (overtime.max(0) + 999) / 1000;
+        seat.time_bank = (seat.time_bank as i64 - used).max(0) as i32;
+        if timed_out {
+            seat.timeouts += 1;
+            seat.sitting_out |= seat.timeouts >= max_timeouts;
+        } else {
+            seat.timeouts = 0;
+        }
+    }
+}
+
+/// Whether the table's last hand has finished but not yet been paid into
+/// the seats' stacks.
+pub fn needs_settling(table: &Table, hand: &Hand) -> bool {
+    hand.status == HandStatus::Complete
+        && table.current_hand_id.as_ref() == Some(&hand.id)
+        && table.settled_hand_id.as_ref() != Some(&hand.id)
+}
+
+/// Moves the chips won and lost in a finished hand onto the players' seats.
+pub fn settle(table: &mut Table, hand: &Hand) {
+    table.settled_hand_id = Some(hand.id.clone());
+    for seat in table.seats.iter_mut() {
+        if let Some(player) = hand.players.iter().find(|p| p.id == seat.player_id) {
+            seat.stack = player.stack;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::schema::model::{fixtures, Player};
+
+    fn table() -> Table {
+        fixtures::table("table")
+    }
+
+    fn seated(seats: &[(&str, i32)]) -> Table {
+        let mut table = table();
+        for (id, seat) in seats {
+            join(&mut table, &(*id).into(), *seat, Decimal::new(1000, 0)).unwrap();
+        }
+        table
+    }
+
+    fn hand(players: &[(&str, i64)]) -> Hand {
+        Hand {
+            players: players
+                .iter()
+                .map(|(id, stack)| Player {
+                    id: (*id).into(),
+                    seat: 0,
+                    stack: Decimal::new(*stack, 0),
+                    cards: vec![],
+                    score: 0.0,
+                    description: String::new(),
+                    best_cards: vec![],
+                    shown: false,
+                    missed_small_blind: false,
+                    missed_big_blind: false,
+                })
+                .collect(),
+            ..fixtures::hand("hand")
+        }
+    }
+
+    #[test]
+    fn players_join_free_seats_within_the_buy_in() {
+        let mut table = seated(&[("a", 3), ("b", 1)]);
+        let seats: Vec<i32> = table.seats.iter().map(|s| s.seat).collect();
+        assert_eq!(seats, vec![1, 3]);
+
+        let buy_in = Decimal::new(1000, 0);
+        assert_eq!(
+            join(&mut table, &"c".into(), 3, buy_in),
+            Err(TableError::SeatTaken { seat: 3 })
+        );
+        assert_eq!(
+            join(&mut table, &"a".into(), 4, buy_in),
+            Err(TableError::AlreadySeated { seat: 3 })
+        );
+        assert_eq!(
+            join(&mut table, &"c".into(), 6, buy_in),
+            Err(TableError::InvalidSeat { max_seats: 6 })
+        );
+        assert_eq!(
+            join(&mut table, &"c".into(), 0, Decimal::new(100, 0)),
+            Err(TableError::BuyInOutOfRange {
+                min: Decimal::new(400, 0),
+                max: Decimal::new(2000, 0)
+            })
+        );
+
+        close(&mut table);
+        assert_eq!(
+            join(&mut table, &"c".into(), 0, buy_in),
+            Err(TableError::Closed)
+        );
+    }
+
+    #[test]
+    fn players_dealt_into_a_hand_cannot_leave_until_it_ends() {
+        let mut table = seated(&[("a", 0), ("b", 1), ("c", 2)]);
+        let current = hand(&[("a", 1000), ("b", 1000)]);
+        assert_eq!(
+            leave(&mut table, &"a".into(), Some(&current)),
+            Err(TableError::InHand)
+        );
+        assert_eq!(
+            leave(&mut table, &"c".into(), Some(&current)).map(|s| s.seat),
+            Ok(2)
+        );
+        assert_eq!(
+            leave(&mut table, &"c".into(), None),
+            Err(TableError::NotSeated)
+        );
+        assert!(leave(&mut table, &"a".into(), None).is_ok());
+    }
+
+    #[test]
+    fn deals_seated_players_and_moves_the_button() {
+        let mut table = seated(&[("a", 0), ("b", 2), ("c", 4)]);
+        table.seats[1].sitting_out = true;
+        let (seats, dealer_seat) = deal_in(&table, None).unwrap();
+        let ids: Vec<&str> = seats.iter().map(|s| s.player_id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "c"]);
+        assert_eq!(dealer_seat, 0);
+
+        table.seats[1].sitting_out = false;
+        table.dealer_seat = Some(0);
+        assert_eq!(deal_in(&table, None).unwrap().1, 2);
+        table.dealer_seat = Some(4);
+        assert_eq!(deal_in(&table, None).unwrap().1, 0);
+
+        // Busted players are skipped, along with the button.
+        table.seats[1].stack = Decimal::ZERO;
+        table.dealer_seat = Some(0);
+        assert_eq!(deal_in(&table, None).unwrap().1, 4);
+    }
+
+    #[test]
+    fn deal_waits_for_the_current_hand_and_enough_players() {
+        let table = seated(&[("a", 0), ("b", 1)]);
+        let current = hand(&[("a", 1000), ("b", 1000)]);
+        assert_eq!(
+            deal_in(&table, Some(&current)),
+            Err(TableError::HandInProgress {
+                hand_id: "hand".into()
+            })
+        );
+        assert_eq!(
+            deal_in(&seated(&[("a", 0)]), None),
+            Err(TableError::NotEnoughPlayers)
+        );
+
+        // Never more hands than the table has seats.
+        let mut crowded = seated(&[("a", 0), ("b", 1), ("c", 2)]);
+        crowded.max_seats = 2;
+        assert_eq!(
+            deal_in(&crowded, None),
+            Err(TableError::TooManyPlayers { max_seats: 2 })
+        );
+    }
+
+    /// Deals the table's next hand and records it, the way the server does.
+    fn deal_next(table: &mut Table) -> Vec<String> {
+        let (seats, dealer_seat) = deal_in(table, None).unwrap();
+        let mut dealt = hand(&[]);
+        dealt.dealer_seat = dealer_seat;
+        dealt.players = seats
+            .iter()
+            .map(|s| Player {
+                id: s.player_id.clone(),
+                seat: s.seat,
+                stack: s.stack,
+                cards: vec![],
+                score: 0.0,
+                description: String::new(),
+                best_cards: vec![],
+                shown: false,
+                missed_small_blind: s.missed_small_blind,
+                missed_big_blind: s.missed_big_blind,
+            })
+            .collect();
+        start(table, &dealt);
+        table.current_hand_id = None;
+        seats.iter().map(|s| s.player_id.to_string()).collect()
+    }
+
+    #[test]
+    fn players_sitting_out_are_charged_the_blinds_they_miss() {
+        let mut table = seated(&[("a", 0), ("b", 1), ("c", 2), ("d", 3)]);
+        table.dealer_seat = Some(0);
+        let d: ID = "d".into();
+        assert!(sit_out(&mut table, &d).unwrap().sitting_out);
+
+        // b has the button, c the small blind and a the big blind.
+        assert_eq!(deal_next(&mut table), vec!["a", "b", "c"]);
+        assert!(table.seats[3].missed_big_blind);
+        assert!(!table.seats[3].missed_small_blind);
+
+        // Button c, small blind a: d misses that too.
+        assert_eq!(deal_next(&mut table), vec!["a", "b", "c"]);
+        assert!(table.seats[3].missed_small_blind);
+
+        // Sitting straight back in posts both with the next hand.
+        let seat = sit_in(&mut table, &d, false).unwrap();
+        assert!(!seat.sitting_out && !seat.wait_for_big_blind);
+        let (seats, _) = deal_in(&table, None).unwrap();
+        assert!(seats[3].missed_small_blind && seats[3].missed_big_blind);
+        assert_eq!(deal_next(&mut table), vec!["a", "b", "c", "d"]);
+        assert!(!table.seats[3].missed_small_blind && !table.seats[3].missed_big_blind);
+
+        assert_eq!(sit_out(&mut table, &"e".into()), Err(TableError::NotSeated));
+    }
+
+    #[test]
+    fn heads_up_only_the_big_blind_passes_players_sitting_out() {
+        let mut table = seated(&[("a", 0), ("b", 1), ("c", 2)]);
+        table.dealer_seat = Some(2);
+        sit_out(&mut table, &"b".into()).unwrap();
+
+        // a has the button and the small blind, c the big blind.
+        assert_eq!(deal_next(&mut table), vec!["a", "c"]);
+        assert_eq!(table.dealer_seat, Some(0));
+        assert!(table.seats[1].missed_big_blind);
+        assert!(!table.seats[1].missed_small_blind);
+
+        // Button c, big blind a: b's seat comes after both.
+        table.seats[1].missed_big_blind = false;
+        assert_eq!(deal_next(&mut table), vec!["a", "c"]);
+        assert_eq!(table.dealer_seat, Some(2));
+        assert!(!table.seats[1].missed_small_blind && !table.seats[1].missed_big_blind);
+    }
+
+    #[test]
+    fn players_can_wait_for_the_big_blind_instead() {
+        let mut table = seated(&[("a", 0), ("b", 1), ("c", 2), ("d", 3)]);
+        table.dealer_seat = Some(0);
+        let d: ID = "d".into();
+        sit_out(&mut table, &d).unwrap();
+        deal_next(&mut table);
+        assert!(sit_in(&mut table, &d, true).unwrap().wait_for_big_blind);
+
+        // d is dealt in once the big blind comes round to seat 3, owing
+        // nothing more.
+        let mut hands = vec![];
+        for _ in 0..4 {
+            hands.push(deal_next(&mut table).len());
+        }
+        assert_eq!(hands, vec![3, 3, 3, 4]);
+        assert_eq!(table.dealer_seat, Some(1));
+        let seat = &table.seats[3];
+        assert!(!seat.wait_for_big_blind && !seat.missed_small_blind && !seat.missed_big_blind);
+
+        // Nobody waits when there is no one else to play.
+        let mut table = seated(&[("a", 0), ("b", 1)]);
+        table.seats[1].missed_big_blind = true;
+        sit_in(&mut table, &"b".into(), true).unwrap();
+        assert_eq!(deal_in(&table, None).unwrap().0.len(), 2);
+    }
+
+    #[test]
+    fn client_seeds_are_mixed_in_seat_order() {
+        let mut table = seated(&[("a", 0), ("b", 1), ("c", 2)]);
+        set_client_seed(&mut table, &"c".into(), "cherry").unwrap();
+        set_client_seed(&mut table, &"a".into(), "apple").unwrap();
+        assert_eq!(client_seed(&table.seats), "apple,cherry");
+
+        let long = "x".repeat(MAX_CLIENT_SEED_LEN + 1);
+        for seed in ["", long.as_str()] {
+            assert_eq!(
+                set_client_seed(&mut table, &"b".into(), seed),
+                Err(TableError::InvalidClientSeed)
+            );
+        }
+        assert_eq!(
+            set_client_seed(&mut table, &"d".into(), "date"),
+            Err(TableError::NotSeated)
+        );
+    }
+
+    #[test]
+    fn time_bank_is_used_and_repeated_timeouts_sit_out() {
+        let mut table = seated(&[("a", 0), ("b", 1)]);
+        assert_eq!(time_bank(&table, &"a".into()), 60);
+
+        record_action(&mut table, &"a".into(), 2500, false);
+        assert_eq!(time_bank(&table, &"a".into()), 57);
+        record_action(&mut table, &"a".into(), 57_000, true);
+        assert_eq!(time_bank(&table, &"a".into()), 0);
+        assert!(!table.seats[0].sitting_out);
+
+        // Acting in time resets the count.
+        record_action(&mut table, &"a".into(), 0, false);
+        record_action(&mut table, &"a".into(), 0, true);
+        assert!(!table.seats[0].sitting_out);
+        record_action(&mut table, &"a".into(), 0, true);
+        assert!(table.seats[0].sitting_out);
+        assert_eq!(table.seats[0].timeouts, 2);
+    }
+
+    #[test]
+    fn settled_hands_update_stacks() {
+        let mut table = seated(&[("a", 0), ("b", 1), ("c", 2)]);
+        settle(&mut table, &hand(&[("a", 1500), ("b", 500)]));
+        let stacks: Vec<Decimal> = table.seats.iter().map(|s| s.stack).collect();
+        assert_eq!(
+            stacks,
+            vec![
+                Decimal::new(1500, 0),
+                Decimal::new(500, 0),
+                Decimal::new(1000, 0)
+            ]
+        );
+    }
+}
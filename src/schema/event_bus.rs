@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use async_graphql::Result;
+use async_trait::async_trait;
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use rdkafka::config::{ClientConfig, RDKafkaLogLevel};
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::Message;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use serde_derive::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use super::simple_broker::{SimpleBroker, Topic};
+use super::{DealEvent, HandEventPayload, TableEvent};
+
+/// An event delivered to subscribers of a table, hand or user topic.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum BusEvent {
+    Deal(DealEvent),
+    Hand(Box<HandEventPayload>),
+    Table(TableEvent),
+}
+
+/// Fans events out to the subscriptions listening on a topic.
+#[async_trait]
+pub trait EventBus: Send + Sync {
+    async fn publish(&self, topic: Topic, event: BusEvent) -> Result<()>;
+
+    fn subscribe(&self, topic: Topic) -> BoxStream<'static, BusEvent>;
+}
+
+pub type SharedEventBus = Arc<dyn EventBus>;
+
+/// Delivers events to subscriptions on this server only.
+pub struct InMemoryEventBus;
+
+#[async_trait]
+impl EventBus for InMemoryEventBus {
+    async fn publish(&self, topic: Topic, event: BusEvent) -> Result<()> {
+        SimpleBroker::publish(topic, event);
+        Ok(())
+    }
+
+    fn subscribe(&self, topic: Topic) -> BoxStream<'static, BusEvent> {
+        SimpleBroker::<BusEvent>::subscribe(topic).boxed()
+    }
+}
+
+/// An event on the wire, with the topic it was published to.
+#[derive(Debug, Deserialize, Serialize)]
+struct KafkaRecord {
+    topic: Topic,
+    event: BusEvent,
+}
+
+/// Publishes events to a Kafka topic as JSON so every server instance
+/// receives them.
+///
+/// Each instance consumes the whole topic in its own consumer group and hands
+/// the events to its local subscriptions, including the ones it published.
+pub struct KafkaEventBus {
+    producer: FutureProducer,
+    kafka_topic: String,
+}
+
+impl KafkaEventBus {
+    pub fn new(
+        brokers: &str,
+        kafka_topic: &str,
+        group_id: &str,
+    ) -> Result<Self, rdkafka::error::KafkaError> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("group.id", group_id)
+            .set("bootstrap.servers", brokers)
+            .set("auto.offset.reset", "latest")
+            .set("enable.partition.eof", "false")
+            .set("session.timeout.ms", "6000")
+            .set("enable.auto.commit", "true")
+            .set_log_level(RDKafkaLogLevel::Debug)
+            .create()?;
+        consumer.subscribe(&[kafka_topic])?;
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", "5000")
+            .create()?;
+
+        tokio::spawn(async move {
+            let mut stream = consumer.stream();
+            while let Some(message) = stream.next().await {
+                let record = match message {
+                    Ok(message) => message
+                        .payload()
+                        .map(serde_json::from_slice::<KafkaRecord>),
+                    Err(e) => {
+                        eprintln!("Error receiving message: {}", e);
+                        continue;
+                    }
+                };
+                match record {
+                    Some(Ok(record)) => SimpleBroker::publish(record.topic, record.event),
+                    Some(Err(e)) => eprintln!("Error decoding event: {}", e),
+                    None => {}
+                }
+            }
+        });
+
+        Ok(KafkaEventBus {
+            producer,
+            kafka_topic: kafka_topic.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl EventBus for KafkaEventBus {
+    async fn publish(&self, topic: Topic, event: BusEvent) -> Result<()> {
+        let key = format!("{:?}", topic);
+        let payload = serde_json::to_string(&KafkaRecord { topic, event })?;
+        self.producer
+            .send(
+                FutureRecord::to(&self.kafka_topic)
+                    .payload(&payload)
+                    .key(&key),
+                Duration::from_secs(0),
+            )
+            .await
+            .map_err(|(e, _)| e)?;
+        Ok(())
+    }
+
+    fn subscribe(&self, topic: Topic) -> BoxStream<'static, BusEvent> {
+        SimpleBroker::<BusEvent>::subscribe(topic).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::schema::MutationType;
+
+    fn deal_event(id: &str) -> BusEvent {
+        BusEvent::Deal(DealEvent {
+            mutation_type: MutationType::Created,
+            id: id.into(),
+            seed_hash: None,
+            client_seed: String::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn in_memory_bus_delivers_to_topic_subscribers() {
+        let bus = InMemoryEventBus;
+        let topic = Topic::Table("bus-table".into());
+        let mut stream = bus.subscribe(topic.clone());
+        bus.publish(Topic::Table("bus-other".into()), deal_event("other"))
+            .await
+            .unwrap();
+        bus.publish(topic, deal_event("hand")).await.unwrap();
+
+        match stream.next().await {
+            Some(BusEvent::Deal(event)) => assert_eq!(event.id, "hand"),
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn kafka_records_round_trip_as_json() {
+        let record = KafkaRecord {
+            topic: Topic::Hand("h1".into()),
+            event: deal_event("h1"),
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        let decoded: KafkaRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.topic, Topic::Hand("h1".into()));
+        assert!(matches!(decoded.event, BusEvent::Deal(event) if event.id == "h1"));
+    }
+}
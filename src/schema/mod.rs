@@ -1,43 +1,43 @@
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
+use async_graphql::connection::{query, Connection, Edge, OpaqueCursor};
 use async_graphql::{Context, Enum, Object, Result, Schema, Subscription, ID};
-use float_ord::FloatOrd;
-use futures::stream::TryStreamExt;
+use chrono::{DateTime, Utc};
 use futures::StreamExt;
 use futures_util::{lock::Mutex, Stream};
-use rdkafka::consumer::{Consumer, StreamConsumer};
-use rust_decimal::Decimal;
-use slab::Slab;
-use rdkafka::message::{Headers, Message};
-
-use tonic::Request;
-use uuid::Uuid;
-
-use mongodb::bson::to_bson;
-use mongodb::bson::{doc, Document};
-use mongodb::Database;
+use serde_derive::{Deserialize, Serialize};
 
+pub mod auth;
+pub mod engine;
+use engine::table;
+pub mod event_bus;
+use event_bus::{BusEvent, SharedEventBus};
 pub mod model;
 use model::{
-    ActivePlayer, Cards, DealInput, Hand, PlayerAction, PlayerEvent, PlayerInput, StreetEvent,
-    StreetType,
+    Cards, Hand, HandLogEntry, HandStatus, HandVerification, PlayerEvent, PotAward, StreetEvent,
+    Table, TableConfig, TableStatus,
+};
+pub mod simple_broker;
+use simple_broker::Topic;
+pub mod storage;
+use storage::{
+    HandCursor, HandFilter, SharedHandLogRepository, SharedHandRepository, SharedTableRepository,
 };
-mod simple_broker;
-use simple_broker::SimpleBroker;
 
 pub mod mutation;
 use mutation::MutationRoot;
+pub mod scheduler;
+pub mod shuffler;
 pub mod deal {
     include!("../deal_app.rs");
 }
 use deal::dealer_client::DealerClient;
 
-use deal::{HandRequest, HandResponse};
-
 pub type PokerSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
 
 pub type DealService = Arc<Mutex<DealerClient<tonic::transport::Channel>>>;
 
+/// The signed in user's id, once their `x-user-token` has been verified.
 #[derive(Debug)]
 pub struct UserToken(pub String);
 
@@ -45,20 +45,22 @@ pub struct TableToken(pub String);
 
 pub struct HandToken(pub String);
 
-#[derive(Enum, Eq, PartialEq, Copy, Clone)]
+#[derive(Enum, Eq, PartialEq, Copy, Clone, Debug, Deserialize, Serialize)]
 pub enum MutationType {
     Created,
     Updated,
 }
 
-#[derive(Clone)]
-struct HandEvent {
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DealEvent {
     mutation_type: MutationType,
     id: ID,
+    seed_hash: Option<String>,
+    client_seed: String,
 }
 
 #[Object]
-impl HandEvent {
+impl DealEvent {
     async fn mutation_type(&self) -> MutationType {
         MutationType::Created
     }
@@ -66,61 +68,68 @@ impl HandEvent {
     async fn id(&self) -> &ID {
         &self.id
     }
-}
-
-#[derive(Clone)]
-struct DealEvent {
-    mutation_type: MutationType,
-    id: ID,
-}
 
-#[Object]
-impl DealEvent {
-    async fn mutation_type(&self) -> MutationType {
-        MutationType::Created
+    /// SHA-256 of the server seed the deck was shuffled with, committed to
+    /// before any card is seen. The seed is revealed when the hand ends.
+    async fn seed_hash(&self) -> Option<&str> {
+        self.seed_hash.as_deref()
     }
 
-    async fn id(&self) -> &ID {
-        &self.id
+    /// The players' seeds mixed into the shuffle.
+    async fn client_seed(&self) -> &str {
+        &self.client_seed
     }
 
     async fn deal(&self, ctx: &Context<'_>) -> Result<Hand> {
-        let db = ctx.data_unchecked::<Database>();
-        let typed_collection = db.collection::<Hand>("hands");
-        let id = self.id.parse::<String>()?;
-        println!("id for mongo: {:#?}", id);
-        let hand_option = typed_collection.find_one(doc! { "id": id }, None).await?;
-        let hand =
-            hand_option.ok_or_else(|| "No document found with the specified id".to_string())?;
-        println!("here is the hand: {:?}", hand);
-        //let mut fcursor = typed_collection.find(doc! { "id": id }, None).await?;
-        //let fhand = fcursor.try_next().await?;
-        //let hand_option = cursor.try_next().await?;
-        //let hand = hand_option.ok_or("No document found with the specified id")?;
-
-        Ok(hand)
+        let hands = ctx.data_unchecked::<SharedHandRepository>();
+        storage::load_hand(hands, &self.id).await
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct HandEventPayload {
     mutation_type: MutationType,
     hand_id: ID,
+    sequence: i64,
+    snapshot: Option<Hand>,
+    log_entry: Option<HandLogEntry>,
     street_event: Option<StreetEvent>,
     player_event: Option<PlayerEvent>,
     cards: Option<Cards>,
+    pot_awards: Vec<PotAward>,
+    to_act: Option<ID>,
+    /// Milliseconds since the Unix epoch.
+    action_deadline: Option<i64>,
+    time_bank: Option<i32>,
 }
 
 #[Object]
 impl HandEventPayload {
+    /// `CREATED` for the snapshot, `UPDATED` for what follows.
     async fn mutation_type(&self) -> MutationType {
-        MutationType::Updated
+        self.mutation_type
     }
 
     async fn hand_id(&self) -> &ID {
         &self.hand_id
     }
 
+    /// The last hand log entry this event brings the hand up to. Resubscribe
+    /// with it as `sinceSequence` to pick up where this left off.
+    async fn sequence(&self) -> i64 {
+        self.sequence
+    }
+
+    /// The whole hand as the viewer may see it, sent first on subscribing.
+    async fn snapshot(&self) -> &Option<Hand> {
+        &self.snapshot
+    }
+
+    /// An entry missed before subscribing, replayed from the hand log.
+    async fn log_entry(&self) -> &Option<HandLogEntry> {
+        &self.log_entry
+    }
+
     async fn street_event(&self) -> &Option<StreetEvent> {
         &self.street_event
     }
@@ -132,14 +141,179 @@ impl HandEventPayload {
     async fn cards(&self) -> &Option<Cards> {
         &self.cards
     }
+
+    async fn pot_awards(&self) -> &[PotAward] {
+        &self.pot_awards
+    }
+
+    /// Who acts next, if anyone.
+    async fn to_act(&self) -> &Option<ID> {
+        &self.to_act
+    }
+
+    /// When the player to act runs out of time and starts on their time bank.
+    async fn action_deadline(&self) -> Option<DateTime<Utc>> {
+        self.action_deadline.map(model::timestamp)
+    }
+
+    /// Seconds of time bank the player to act has after the deadline.
+    async fn time_bank(&self) -> Option<i32> {
+        self.time_bank
+    }
+}
+
+impl HandEventPayload {
+    /// The hand as it stands after log entry `sequence`.
+    fn of_hand(hand: Hand, sequence: i64, time_bank: Option<i32>) -> Self {
+        HandEventPayload {
+            mutation_type: MutationType::Created,
+            hand_id: hand.id.clone(),
+            sequence,
+            street_event: hand.street_events.last().cloned(),
+            player_event: None,
+            cards: Some(hand.cards.clone()),
+            pot_awards: hand.pot_awards.clone(),
+            to_act: hand.to_act.clone(),
+            action_deadline: hand.action_deadline,
+            time_bank,
+            snapshot: Some(hand),
+            log_entry: None,
+        }
+    }
+
+    fn of_log_entry(entry: HandLogEntry) -> Self {
+        HandEventPayload {
+            mutation_type: MutationType::Updated,
+            hand_id: entry.hand_id.clone(),
+            sequence: entry.sequence,
+            snapshot: None,
+            street_event: None,
+            player_event: None,
+            cards: None,
+            pot_awards: vec![],
+            to_act: None,
+            action_deadline: None,
+            time_bank: None,
+            log_entry: Some(entry),
+        }
+    }
+}
+
+/// What a table's next-hand scheduler is doing.
+#[derive(Enum, Eq, PartialEq, Copy, Clone, Debug, Deserialize, Serialize)]
+pub enum TableEventKind {
+    /// The next hand is being counted down to.
+    Countdown,
+    /// The countdown stopped because too few players are ready.
+    Paused,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TableEvent {
+    pub kind: TableEventKind,
+    pub table_id: ID,
+    /// Seconds until the next hand is dealt, while counting down.
+    pub seconds_remaining: Option<i32>,
+}
+
+#[Object]
+impl TableEvent {
+    async fn kind(&self) -> TableEventKind {
+        self.kind
+    }
+
+    async fn table_id(&self) -> &ID {
+        &self.table_id
+    }
+
+    async fn seconds_remaining(&self) -> Option<i32> {
+        self.seconds_remaining
+    }
 }
 
 pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
-    async fn hands(&self, ctx: &Context<'_>) -> Vec<Hand> {
-        Vec::new()
+    /// Hands newest first, optionally narrowed to a table, a player, a time
+    /// range or a status.
+    #[allow(clippy::too_many_arguments)]
+    async fn hands(
+        &self,
+        ctx: &Context<'_>,
+        table_id: Option<ID>,
+        player_id: Option<ID>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        status: Option<HandStatus>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<Connection<OpaqueCursor<HandCursor>, Hand>> {
+        let hands = ctx.data_unchecked::<SharedHandRepository>();
+        let filter = HandFilter {
+            table_id,
+            player_id,
+            from: from.map(|from| from.timestamp_millis()),
+            to: to.map(|to| to.timestamp_millis()),
+            status,
+        };
+        query(
+            after,
+            None,
+            first,
+            None,
+            |after: Option<OpaqueCursor<HandCursor>>, _, first, _| async move {
+                let limit = first.unwrap_or(20).min(100);
+                let mut page = hands
+                    .list(&filter, after.as_deref(), limit as i64 + 1)
+                    .await?;
+                let has_next_page = page.len() > limit;
+                page.truncate(limit);
+
+                let mut connection = Connection::new(after.is_some(), has_next_page);
+                connection.edges.extend(
+                    page.into_iter()
+                        .map(|hand| Edge::new(OpaqueCursor(HandCursor::of(&hand)), hand)),
+                );
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
+
+    async fn hand(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Hand>> {
+        let hands = ctx.data_unchecked::<SharedHandRepository>();
+        hands.find(&id).await
+    }
+
+    /// Everything that happened in a hand, in order, for audits and disputes.
+    async fn hand_events(&self, ctx: &Context<'_>, hand_id: ID) -> Result<Vec<HandLogEntry>> {
+        let hand_log = ctx.data_unchecked::<SharedHandLogRepository>();
+        hand_log.find(&hand_id).await
+    }
+
+    /// Re-shuffles a finished hand's deck from its revealed seed, checking it
+    /// against the commitment published with the deal and the cards dealt.
+    async fn verify_hand(&self, ctx: &Context<'_>, id: ID) -> Result<HandVerification> {
+        let hands = ctx.data_unchecked::<SharedHandRepository>();
+        let hand = storage::load_hand(hands, &id).await?;
+        Ok(shuffler::verify(&hand)?)
+    }
+
+    /// Tables with the status, open ones unless another is asked for.
+    async fn tables(&self, ctx: &Context<'_>, status: Option<TableStatus>) -> Result<Vec<Table>> {
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        tables.list(status.unwrap_or_default()).await
+    }
+
+    async fn table(&self, ctx: &Context<'_>, id: ID) -> Result<Option<Table>> {
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        tables.find(&id).await
+    }
+
+    async fn table_config(&self, ctx: &Context<'_>, table_id: ID) -> Result<TableConfig> {
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        Ok(storage::load_table(tables, &table_id).await?.config)
     }
 }
 
@@ -151,67 +325,111 @@ impl SubscriptionRoot {
         &self,
         ctx: &Context<'_>,
         mutation_type: Option<MutationType>,
-    ) -> impl Stream<Item = DealEvent> {
+    ) -> Result<impl Stream<Item = DealEvent>> {
         println!("SubscriptionRoot::deal");
 
-        let user_token = ctx.data::<UserToken>().unwrap().0.clone();
-        let table_token = ctx.data::<TableToken>().unwrap().0.clone();
-
-        println!("user_token: {}", user_token);
-        println!("table_token: {}", table_token);
-
-        SimpleBroker::<DealEvent>::subscribe().filter(move |event| {
-            let res = if let Some(mutation_type) = mutation_type {
-                event.mutation_type == mutation_type
-            } else {
-                true
+        // Without a table, follow the player to whichever table deals them in.
+        let topic = match (ctx.data_opt::<TableToken>(), ctx.data_opt::<UserToken>()) {
+            (Some(table_token), _) => Topic::Table(table_token.0.clone()),
+            (None, Some(user_token)) => Topic::User(user_token.0.clone()),
+            (None, None) => return Err("x-table-token or x-user-token is required".into()),
+        };
+        println!("deal topic: {:?}", topic);
+
+        let bus = ctx.data_unchecked::<SharedEventBus>();
+        Ok(bus.subscribe(topic).filter_map(move |event| {
+            let res = match event {
+                BusEvent::Deal(event)
+                    if mutation_type.is_none_or(|m| event.mutation_type == m) =>
+                {
+                    Some(event)
+                }
+                _ => None,
             };
             async move { res }
-        })
-    }
-
-    async fn kafka_test<'a>(&self, ctx: &'a Context<'a>) -> impl Stream<Item = String> + 'a {
-        let consumer: &'a StreamConsumer = ctx.data_unchecked::<StreamConsumer>();
-        let message_stream = consumer.stream();
-    
-        message_stream.filter_map(|message_result| {
-            async move {
-                match message_result {
-                    Ok(message) => {
-                        message.payload_view::<str>().unwrap_or(Ok("")).ok().map(|s| s.to_string())
-                    }
-                    Err(e) => {
-                        eprintln!("Error receiving message: {}", e);
-                        None
-                    }
-                }
-            }
-        })
+        }))
     }
 
+    /// The hand's events as they happen, starting with a snapshot of the
+    /// hand so far. With `sinceSequence`, the log entries after it are
+    /// replayed between the snapshot and the live events, for clients
+    /// catching up after a dropped connection.
     async fn hand_event(
         &self,
         ctx: &Context<'_>,
         mutation_type: Option<MutationType>,
-    ) -> impl Stream<Item = HandEventPayload> {
+        since_sequence: Option<i64>,
+    ) -> Result<impl Stream<Item = HandEventPayload>> {
         println!("SubscriptionRoot::hand_event");
 
-        let user_token = ctx.data::<UserToken>().unwrap().0.clone();
-        let table_token = ctx.data::<TableToken>().unwrap().0.clone();
-        let hand_token = ctx.data::<HandToken>().unwrap().0.clone();
-
-        println!("user_token: {}", user_token);
-        println!("table_token: {}", table_token);
+        let hand_token = ctx
+            .data_opt::<HandToken>()
+            .ok_or("x-hand-token is required")?
+            .0
+            .clone();
         println!("hand_token: {}", hand_token);
 
-        SimpleBroker::<HandEventPayload>::subscribe().filter(move |event| {
-            let res = if let Some(mutation_type) = mutation_type {
-                event.mutation_type == mutation_type
-            } else {
-                true
+        // Listen before reading the hand, so nothing published in between
+        // is lost.
+        let bus = ctx.data_unchecked::<SharedEventBus>();
+        let live = bus.subscribe(Topic::Hand(hand_token.clone()));
+
+        let hands = ctx.data_unchecked::<SharedHandRepository>();
+        let hand_log = ctx.data_unchecked::<SharedHandLogRepository>();
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        let hand = storage::load_hand(hands, &hand_token.into()).await?;
+        let entries = hand_log.find(&hand.id).await?;
+        let sequence = entries.last().map_or(0, |entry| entry.sequence);
+        let time_bank = match (&hand.to_act, tables.find(&hand.table_id).await?) {
+            (Some(to_act), Some(table)) => Some(table::time_bank(&table, to_act)),
+            _ => None,
+        };
+
+        let mut missed = vec![HandEventPayload::of_hand(hand, sequence, time_bank)];
+        if let Some(since) = since_sequence {
+            missed.extend(
+                entries
+                    .into_iter()
+                    .filter(|entry| entry.sequence > since)
+                    .map(HandEventPayload::of_log_entry),
+            );
+        }
+        // The snapshot is `CREATED` and what was missed `UPDATED`, filtered
+        // the same as the live events.
+        missed.retain(|event| mutation_type.is_none_or(|m| event.mutation_type == m));
+        let live = live.filter_map(move |event| {
+            let res = match event {
+                BusEvent::Hand(event)
+                    if event.sequence > sequence
+                        && mutation_type.is_none_or(|m| event.mutation_type == m) =>
+                {
+                    Some(*event)
+                }
+                _ => None,
+            };
+            async move { res }
+        });
+        Ok(futures::stream::iter(missed).chain(live))
+    }
+
+    /// The countdown to the table's next hand.
+    async fn table_event(&self, ctx: &Context<'_>) -> Result<impl Stream<Item = TableEvent>> {
+        println!("SubscriptionRoot::table_event");
+
+        let table_token = ctx
+            .data_opt::<TableToken>()
+            .ok_or("x-table-token is required")?
+            .0
+            .clone();
+
+        let bus = ctx.data_unchecked::<SharedEventBus>();
+        Ok(bus.subscribe(Topic::Table(table_token)).filter_map(|event| {
+            let res = match event {
+                BusEvent::Table(event) => Some(event),
+                _ => None,
             };
             async move { res }
-        })
+        }))
     }
 
     // HandEventPayload
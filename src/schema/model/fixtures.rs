@@ -0,0 +1,49 @@
+//! Tables and hands for tests to start from, changing only the fields a test
+//! cares about.
+
+use super::{Cards, Hand, HandStatus, Table, TableConfig, TableStatus};
+
+/// An open six seat table with nobody seated and the default config.
+pub fn table(id: &str) -> Table {
+    Table {
+        id: id.into(),
+        name: id.to_string(),
+        owner_id: "owner".into(),
+        version: 0,
+        created_at: 0,
+        status: TableStatus::Open,
+        max_seats: 6,
+        config: TableConfig::default(),
+        seats: vec![],
+        dealer_seat: None,
+        current_hand_id: None,
+        settled_hand_id: None,
+        next_seed: None,
+    }
+}
+
+/// A hand in progress at "table" with no players, cards or actions yet.
+pub fn hand(id: &str) -> Hand {
+    Hand {
+        id: id.into(),
+        table_id: "table".into(),
+        version: 0,
+        log_sequence: 0,
+        created_at: 0,
+        players: vec![],
+        cards: Cards::default(),
+        deck: vec![],
+        config: TableConfig::default(),
+        pot_awards: vec![],
+        status: HandStatus::InProgress,
+        dealer_seat: 0,
+        to_act: None,
+        last_aggressor: None,
+        action_deadline: None,
+        seed: None,
+        seed_hash: None,
+        client_seed: String::new(),
+        player_events: vec![],
+        street_events: vec![],
+    }
+}
@@ -1,13 +1,62 @@
 use async_graphql::*;
+use chrono::{DateTime, TimeZone, Utc};
 use rust_decimal::Decimal;
 
+use super::shuffler;
+use super::UserToken;
+
 use serde_derive::{Deserialize, Serialize};
+
+#[cfg(test)]
+pub mod fixtures;
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Hand {
     pub id: ID,
     pub table_id: ID,
+    /// Bumped on every save, so concurrent writers can't overwrite each other.
+    #[serde(default)]
+    pub version: i64,
+    /// The sequence of the last entry in the hand's log. It is claimed with
+    /// each save, so only the writer whose save wins numbers the next entries.
+    #[serde(default)]
+    pub log_sequence: i64,
+    /// When the hand was dealt, in milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub created_at: i64,
     pub players: Vec<Player>,
+    /// The community cards dealt so far.
     pub cards: Cards,
+    /// What is left of the deck, top card first, to deal the rest of the
+    /// board from. Never sent to clients.
+    #[serde(default)]
+    pub deck: Vec<String>,
+    #[serde(default)]
+    pub config: TableConfig,
+    #[serde(default)]
+    pub pot_awards: Vec<PotAward>,
+    #[serde(default)]
+    pub status: HandStatus,
+    #[serde(default)]
+    pub dealer_seat: i32,
+    #[serde(default)]
+    pub to_act: Option<ID>,
+    #[serde(default)]
+    pub last_aggressor: Option<ID>,
+    /// When the player to act runs out of time, before any time bank, in
+    /// milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub action_deadline: Option<i64>,
+    /// What the deck was shuffled with, hex encoded, if the shuffler used a
+    /// seed.
+    #[serde(default)]
+    pub seed: Option<String>,
+    /// SHA-256 of the seed, published when the hand is dealt.
+    #[serde(default)]
+    pub seed_hash: Option<String>,
+    /// The seated players' client seeds, mixed into the shuffle.
+    #[serde(default)]
+    pub client_seed: String,
     pub player_events: Vec<PlayerEvent>,
     pub street_events: Vec<StreetEvent>,
 }
@@ -22,6 +71,14 @@ impl Hand {
         &self.table_id
     }
 
+    async fn version(&self) -> i64 {
+        self.version
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        timestamp(self.created_at)
+    }
+
     async fn players(&self) -> &[Player] {
         &self.players
     }
@@ -30,6 +87,52 @@ impl Hand {
         &self.cards
     }
 
+    async fn config(&self) -> &TableConfig {
+        &self.config
+    }
+
+    async fn pot_awards(&self) -> &[PotAward] {
+        &self.pot_awards
+    }
+
+    async fn status(&self) -> HandStatus {
+        self.status
+    }
+
+    async fn dealer_seat(&self) -> i32 {
+        self.dealer_seat
+    }
+
+    async fn to_act(&self) -> Option<&ID> {
+        self.to_act.as_ref()
+    }
+
+    async fn last_aggressor(&self) -> Option<&ID> {
+        self.last_aggressor.as_ref()
+    }
+
+    async fn action_deadline(&self) -> Option<DateTime<Utc>> {
+        self.action_deadline.map(timestamp)
+    }
+
+    /// The seed the deck was shuffled with, once the hand is over. Until
+    /// then it would give every card away.
+    async fn seed(&self) -> Option<&str> {
+        match self.status {
+            HandStatus::Complete => self.seed.as_deref(),
+            HandStatus::InProgress => None,
+        }
+    }
+
+    /// The hash of the seed, to check it against once it is revealed.
+    async fn seed_hash(&self) -> Option<&str> {
+        self.seed_hash.as_deref()
+    }
+
+    async fn client_seed(&self) -> &str {
+        &self.client_seed
+    }
+
     async fn player_events(&self) -> &[PlayerEvent] {
         &self.player_events
     }
@@ -39,7 +142,127 @@ impl Hand {
     }
 }
 
+/// The outcome of re-shuffling a finished hand's deck from its revealed
+/// seed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandVerification {
+    pub hand_id: ID,
+    pub seed: String,
+    pub seed_hash: String,
+    pub client_seed: String,
+    /// The deck the seeds shuffle to, top card first.
+    pub deck: Vec<String>,
+    /// Whether the seed hashes to the commitment published with the deal.
+    pub commitment_matches: bool,
+    /// Whether the hole cards and board were dealt from the top of the deck.
+    pub cards_match: bool,
+}
+
+#[Object]
+impl HandVerification {
+    async fn hand_id(&self) -> &ID {
+        &self.hand_id
+    }
+
+    async fn seed(&self) -> &str {
+        &self.seed
+    }
+
+    async fn seed_hash(&self) -> &str {
+        &self.seed_hash
+    }
+
+    async fn client_seed(&self) -> &str {
+        &self.client_seed
+    }
+
+    async fn deck(&self) -> &[String] {
+        &self.deck
+    }
+
+    async fn commitment_matches(&self) -> bool {
+        self.commitment_matches
+    }
+
+    async fn cards_match(&self) -> bool {
+        self.cards_match
+    }
+
+    /// Both checks passed: the deck was fixed before the deal and dealt
+    /// as committed.
+    async fn verified(&self) -> bool {
+        self.commitment_matches && self.cards_match
+    }
+}
+
+pub fn timestamp(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(millis).single().unwrap_or_default()
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PotAward {
+    pub amount: Decimal,
+    pub eligible_player_ids: Vec<ID>,
+    pub winner_ids: Vec<ID>,
+    pub shares: Vec<PotShare>,
+}
+
+#[Object]
+impl PotAward {
+    async fn amount(&self) -> Decimal {
+        self.amount
+    }
+
+    async fn eligible_player_ids(&self) -> &[ID] {
+        &self.eligible_player_ids
+    }
+
+    async fn winner_ids(&self) -> &[ID] {
+        &self.winner_ids
+    }
+
+    async fn shares(&self) -> &[PotShare] {
+        &self.shares
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct PotShare {
+    pub player_id: ID,
+    pub amount: Decimal,
+}
+
+#[Object]
+impl PotShare {
+    async fn player_id(&self) -> &ID {
+        &self.player_id
+    }
+
+    async fn amount(&self) -> Decimal {
+        self.amount
+    }
+}
+
+/// Who receives the chips left over when a pot can't be split evenly.
+#[derive(Debug, Default, Enum, Eq, PartialEq, Copy, Clone, Deserialize, Serialize)]
+pub enum OddChipRule {
+    /// The first tied winner clockwise from the button.
+    #[default]
+    FirstLeftOfButton,
+    /// The tied winner holding the highest card, ties broken by suit with
+    /// spades over hearts over diamonds over clubs.
+    HighCardBySuit,
+}
+
+/// Whether a hand is still being played or has been settled.
+#[derive(Debug, Default, Enum, Eq, PartialEq, Copy, Clone, Deserialize, Serialize)]
+pub enum HandStatus {
+    #[default]
+    InProgress,
+    Complete,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
 pub struct Cards {
     pub flop: Vec<String>,
     pub turn: String,
@@ -103,6 +326,11 @@ pub enum PlayerAction {
     Bet,
     Check,
     Fold,
+    Call,
+    Raise,
+    AllIn,
+    /// A forced bet: blind, ante or straddle. Never sent by players.
+    Post,
 }
 
 #[derive(Debug, Enum, Eq, PartialEq, Copy, Clone, Deserialize, Serialize)]
@@ -117,7 +345,11 @@ pub enum StreetType {
 pub struct StreetEvent {
     pub street_type: StreetType,
     pub current_active_players: Vec<ActivePlayer>,
-    pub pot: Decimal
+    pub pot: Decimal,
+    #[serde(default)]
+    pub min_raise: Decimal,
+    #[serde(default)]
+    pub to_act: Option<ID>,
 }
 
 #[Object]
@@ -134,15 +366,26 @@ impl StreetEvent {
         self.pot
     }
 
+    async fn min_raise(&self) -> Decimal {
+        self.min_raise
+    }
+
+    async fn to_act(&self) -> Option<&ID> {
+        self.to_act.as_ref()
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct ActivePlayer {
     pub id: ID,
+    #[serde(default)]
+    pub seat: i32,
     pub bet: Decimal,
     pub stack: Decimal,
     pub is_inactive: bool,
     pub is_big_blind: bool,
+    #[serde(default)]
+    pub has_acted: bool,
 }
 
 #[Object]
@@ -151,6 +394,10 @@ impl ActivePlayer {
         &self.id
     }
 
+    async fn seat(&self) -> i32 {
+        self.seat
+    }
+
     async fn bet(&self) -> Decimal {
         self.bet
     }
@@ -166,21 +413,355 @@ impl ActivePlayer {
     async fn is_big_blind(&self) -> bool {
         self.is_big_blind
     }
+
+    async fn has_acted(&self) -> bool {
+        self.has_acted
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, InputObject)]
 pub struct DealInput {
-    pub players: Vec<PlayerInput>,
     pub table_id: ID,
+    /// Whether the player under the gun posts a straddle, if the table allows it.
+    pub straddle: Option<bool>,
+}
+
+/// Stakes and settlement rules for a table, copied onto each hand it deals.
+#[derive(Clone, Debug, PartialEq, InputObject, Deserialize, Serialize)]
+#[graphql(input_name = "TableConfigInput")]
+pub struct TableConfig {
+    pub small_blind: Decimal,
+    pub big_blind: Decimal,
+    /// Posted by every player, or by the big blind alone with `big_blind_ante`.
+    pub ante: Decimal,
+    pub big_blind_ante: bool,
+    pub straddle_allowed: bool,
+    pub min_buy_in: Decimal,
+    pub max_buy_in: Decimal,
+    pub odd_chip_rule: OddChipRule,
+    /// The smallest chip in play; pots are never split below it.
+    pub chip_denomination: Decimal,
+    /// Seconds from one hand settling to the next being dealt.
+    #[serde(default = "default_next_hand_delay")]
+    #[graphql(default = 5)]
+    pub next_hand_delay: i32,
+    /// Seconds a player has to act before their time bank is used.
+    #[serde(default = "default_action_timeout")]
+    #[graphql(default = 30)]
+    pub action_timeout: i32,
+    /// Seconds of extra time each player brings to the table.
+    #[serde(default = "default_time_bank")]
+    #[graphql(default = 60)]
+    pub time_bank: i32,
+    /// Timeouts in a row before a player is sat out.
+    #[serde(default = "default_max_timeouts")]
+    #[graphql(default = 2)]
+    pub max_timeouts: i32,
+}
+
+fn default_next_hand_delay() -> i32 {
+    5
+}
+
+fn default_action_timeout() -> i32 {
+    30
+}
+
+fn default_time_bank() -> i32 {
+    60
+}
+
+fn default_max_timeouts() -> i32 {
+    2
+}
+
+impl Default for TableConfig {
+    fn default() -> Self {
+        TableConfig {
+            small_blind: Decimal::new(10, 0),
+            big_blind: Decimal::new(20, 0),
+            ante: Decimal::ZERO,
+            big_blind_ante: false,
+            straddle_allowed: false,
+            min_buy_in: Decimal::new(400, 0),
+            max_buy_in: Decimal::new(2000, 0),
+            odd_chip_rule: OddChipRule::FirstLeftOfButton,
+            chip_denomination: Decimal::ONE,
+            next_hand_delay: default_next_hand_delay(),
+            action_timeout: default_action_timeout(),
+            time_bank: default_time_bank(),
+            max_timeouts: default_max_timeouts(),
+        }
+    }
+}
+
+impl TableConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.small_blind <= Decimal::ZERO || self.big_blind < self.small_blind {
+            return Err("Big blind must be at least the small blind, which must be positive".into());
+        }
+        if self.ante < Decimal::ZERO {
+            return Err("Ante cannot be negative".into());
+        }
+        if self.chip_denomination <= Decimal::ZERO {
+            return Err("Chip denomination must be positive".into());
+        }
+        if self.min_buy_in > self.max_buy_in {
+            return Err("Minimum buy-in cannot exceed the maximum buy-in".into());
+        }
+        if self.next_hand_delay < 0 || self.time_bank < 0 {
+            return Err("Next hand delay and time bank cannot be negative".into());
+        }
+        if self.action_timeout <= 0 || self.max_timeouts <= 0 {
+            return Err("Action timeout and max timeouts must be positive".into());
+        }
+        Ok(())
+    }
+}
+
+#[Object]
+impl TableConfig {
+    async fn small_blind(&self) -> Decimal {
+        self.small_blind
+    }
+
+    async fn big_blind(&self) -> Decimal {
+        self.big_blind
+    }
+
+    async fn ante(&self) -> Decimal {
+        self.ante
+    }
+
+    async fn big_blind_ante(&self) -> bool {
+        self.big_blind_ante
+    }
+
+    async fn straddle_allowed(&self) -> bool {
+        self.straddle_allowed
+    }
+
+    async fn min_buy_in(&self) -> Decimal {
+        self.min_buy_in
+    }
+
+    async fn max_buy_in(&self) -> Decimal {
+        self.max_buy_in
+    }
+
+    async fn odd_chip_rule(&self) -> OddChipRule {
+        self.odd_chip_rule
+    }
+
+    async fn chip_denomination(&self) -> Decimal {
+        self.chip_denomination
+    }
+
+    async fn next_hand_delay(&self) -> i32 {
+        self.next_hand_delay
+    }
+
+    async fn action_timeout(&self) -> i32 {
+        self.action_timeout
+    }
+
+    async fn time_bank(&self) -> i32 {
+        self.time_bank
+    }
+
+    async fn max_timeouts(&self) -> i32 {
+        self.max_timeouts
+    }
+}
+
+/// A table players sit down at, which deals its hands to the seated players.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Table {
+    pub id: ID,
+    pub name: String,
+    /// The player who created the table and may configure or close it.
+    pub owner_id: ID,
+    /// Bumped on every save, so concurrent writers can't overwrite each other.
+    pub version: i64,
+    pub created_at: i64,
+    pub status: TableStatus,
+    pub max_seats: i32,
+    pub config: TableConfig,
+    /// Occupied seats, in seat order.
+    pub seats: Vec<Seat>,
+    /// Where the button sat for the last hand, if one has been dealt.
+    pub dealer_seat: Option<i32>,
+    pub current_hand_id: Option<ID>,
+    /// The last hand whose result has been paid into the seats' stacks.
+    #[serde(default)]
+    pub settled_hand_id: Option<ID>,
+    /// The server seed the next hand will be shuffled with, hex encoded.
+    /// Only its hash is shown until that hand is over.
+    #[serde(default)]
+    pub next_seed: Option<String>,
+}
+
+#[Object]
+impl Table {
+    async fn id(&self) -> &ID {
+        &self.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn owner_id(&self) -> &ID {
+        &self.owner_id
+    }
+
+    async fn version(&self) -> i64 {
+        self.version
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        timestamp(self.created_at)
+    }
+
+    async fn status(&self) -> TableStatus {
+        self.status
+    }
+
+    async fn max_seats(&self) -> i32 {
+        self.max_seats
+    }
+
+    async fn config(&self) -> &TableConfig {
+        &self.config
+    }
+
+    async fn seats(&self) -> &[Seat] {
+        &self.seats
+    }
+
+    async fn dealer_seat(&self) -> Option<i32> {
+        self.dealer_seat
+    }
+
+    async fn current_hand_id(&self) -> &Option<ID> {
+        &self.current_hand_id
+    }
+
+    /// The hash the next hand's `seedHash` will be, published before the
+    /// players' client seeds for that hand are known.
+    async fn next_seed_hash(&self) -> Option<String> {
+        self.next_seed
+            .as_deref()
+            .and_then(|seed| shuffler::commitment(seed).ok())
+    }
+}
+
+/// Whether a table is taking players and dealing hands.
+#[derive(Debug, Default, Enum, Eq, PartialEq, Copy, Clone, Deserialize, Serialize)]
+pub enum TableStatus {
+    #[default]
+    Open,
+    Closed,
+}
+
+/// A player sitting at a table with the chips they have in front of them.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Seat {
+    pub seat: i32,
+    pub player_id: ID,
+    pub stack: Decimal,
+    /// Players sitting out keep their seat but are not dealt in.
+    #[serde(default)]
+    pub sitting_out: bool,
+    /// Seconds of time bank left.
+    #[serde(default)]
+    pub time_bank: i32,
+    /// Times in a row the player's clock ran out.
+    #[serde(default)]
+    pub timeouts: i32,
+    /// Blinds that passed the seat while the player was sitting out, owed
+    /// when they return.
+    #[serde(default)]
+    pub missed_small_blind: bool,
+    #[serde(default)]
+    pub missed_big_blind: bool,
+    /// Whether the returning player sits out until the big blind reaches
+    /// them, rather than posting what they missed.
+    #[serde(default)]
+    pub wait_for_big_blind: bool,
+    /// Mixed into the shuffle of every hand the player is dealt.
+    #[serde(default)]
+    pub client_seed: Option<String>,
+}
+
+#[Object]
+impl Seat {
+    async fn seat(&self) -> i32 {
+        self.seat
+    }
+
+    async fn player_id(&self) -> &ID {
+        &self.player_id
+    }
+
+    async fn stack(&self) -> Decimal {
+        self.stack
+    }
+
+    async fn sitting_out(&self) -> bool {
+        self.sitting_out
+    }
+
+    async fn time_bank(&self) -> i32 {
+        self.time_bank
+    }
+
+    async fn timeouts(&self) -> i32 {
+        self.timeouts
+    }
+
+    async fn missed_small_blind(&self) -> bool {
+        self.missed_small_blind
+    }
+
+    async fn missed_big_blind(&self) -> bool {
+        self.missed_big_blind
+    }
+
+    async fn wait_for_big_blind(&self) -> bool {
+        self.wait_for_big_blind
+    }
+
+    async fn client_seed(&self) -> Option<&str> {
+        self.client_seed.as_deref()
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, InputObject, Deserialize, Serialize)]
 pub struct Player {
     pub id: ID,
+    #[serde(default)]
+    pub seat: i32,
     pub stack: Decimal,
     pub cards: Vec<String>,
+    /// For display only; pots are awarded by comparing evaluated hands.
     pub score: f64,
     pub description: String,
+    /// The five cards that make the player's hand, ranked at showdown.
+    #[serde(default)]
+    #[graphql(default)]
+    pub best_cards: Vec<String>,
+    /// Whether the player showed their cards at showdown.
+    #[serde(default)]
+    pub shown: bool,
+    /// Blinds missed while sitting out, posted on returning: the small blind
+    /// as dead money and the big blind as a live bet.
+    #[serde(default)]
+    #[graphql(default)]
+    pub missed_small_blind: bool,
+    #[serde(default)]
+    #[graphql(default)]
+    pub missed_big_blind: bool,
 }
 
 #[Object]
@@ -189,25 +770,219 @@ impl Player {
         &self.id
     }
 
+    async fn seat(&self) -> i32 {
+        self.seat
+    }
+
     async fn stack(&self) -> Decimal {
         self.stack
     }
 
-    async fn cards(&self) -> &[String] {
-        &self.cards
+    /// Hole cards, visible to their owner or to everyone once shown.
+    async fn cards(&self, ctx: &Context<'_>) -> Vec<String> {
+        let viewer = ctx.data_opt::<UserToken>().map(|token| token.0.as_str());
+        if self.is_visible_to(viewer) {
+            self.cards.clone()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Hand strength against the board, ranked and revealed at showdown.
+    async fn score(&self) -> Option<f64> {
+        self.shown.then_some(self.score)
+    }
+
+    /// Such as "Two Pair, Kings and Sevens, Ace kicker".
+    async fn description(&self) -> Option<&str> {
+        self.shown.then_some(self.description.as_str())
     }
 
-    async fn score(&self) -> f64 {
-        self.score
+    async fn best_cards(&self) -> Option<&[String]> {
+        self.shown.then_some(self.best_cards.as_slice())
     }
 
-    async fn description(&self) -> &str {
-        &self.description
+    async fn shown(&self) -> bool {
+        self.shown
     }
 }
 
-#[derive(Clone, Debug, PartialEq, InputObject)]
-pub struct PlayerInput {
-    pub id: ID,
-    pub stack: Decimal
-}
\ No newline at end of file
+impl Player {
+    pub fn is_visible_to(&self, viewer: Option<&str>) -> bool {
+        self.shown || viewer == Some(self.id.as_str())
+    }
+}
+
+/// One entry in a hand's append-only audit log.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct HandLogEntry {
+    pub hand_id: ID,
+    pub sequence: i64,
+    pub event: HandLogEvent,
+}
+
+/// Something that happened in a hand. Replaying the deal and the actions
+/// rebuilds the hand; the rest record what the dealer did in response.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum HandLogEvent {
+    Dealt(Box<DealtEvent>),
+    BlindPosted(PlayerEvent),
+    PlayerActed(PlayerEvent),
+    StreetDealt(StreetDealtEvent),
+    Showdown(Vec<Player>),
+    PotAwarded(PotAward),
+}
+
+#[derive(Debug, Enum, Eq, PartialEq, Copy, Clone)]
+pub enum HandLogKind {
+    Dealt,
+    BlindPosted,
+    PlayerActed,
+    StreetDealt,
+    Showdown,
+    PotAwarded,
+}
+
+#[Object]
+impl HandLogEntry {
+    async fn hand_id(&self) -> &ID {
+        &self.hand_id
+    }
+
+    async fn sequence(&self) -> i64 {
+        self.sequence
+    }
+
+    async fn kind(&self) -> HandLogKind {
+        match self.event {
+            HandLogEvent::Dealt(_) => HandLogKind::Dealt,
+            HandLogEvent::BlindPosted(_) => HandLogKind::BlindPosted,
+            HandLogEvent::PlayerActed(_) => HandLogKind::PlayerActed,
+            HandLogEvent::StreetDealt(_) => HandLogKind::StreetDealt,
+            HandLogEvent::Showdown(_) => HandLogKind::Showdown,
+            HandLogEvent::PotAwarded(_) => HandLogKind::PotAwarded,
+        }
+    }
+
+    async fn dealt(&self) -> Option<&DealtEvent> {
+        match &self.event {
+            HandLogEvent::Dealt(dealt) => Some(dealt.as_ref()),
+            _ => None,
+        }
+    }
+
+    async fn player_event(&self) -> Option<&PlayerEvent> {
+        match &self.event {
+            HandLogEvent::BlindPosted(event) | HandLogEvent::PlayerActed(event) => Some(event),
+            _ => None,
+        }
+    }
+
+    async fn street_dealt(&self) -> Option<&StreetDealtEvent> {
+        match &self.event {
+            HandLogEvent::StreetDealt(street) => Some(street),
+            _ => None,
+        }
+    }
+
+    async fn shown_players(&self) -> &[Player] {
+        match &self.event {
+            HandLogEvent::Showdown(players) => players,
+            _ => &[],
+        }
+    }
+
+    async fn pot_award(&self) -> Option<&PotAward> {
+        match &self.event {
+            HandLogEvent::PotAwarded(award) => Some(award),
+            _ => None,
+        }
+    }
+}
+
+/// Everything the hand was started from, so it can be dealt again exactly.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DealtEvent {
+    pub table_id: ID,
+    #[serde(default)]
+    pub created_at: i64,
+    pub players: Vec<Player>,
+    /// The deck the board is dealt from. Kept for replays, never sent to
+    /// clients.
+    #[serde(default)]
+    pub deck: Vec<String>,
+    pub config: TableConfig,
+    pub dealer_seat: i32,
+    pub straddle: bool,
+    #[serde(default)]
+    pub action_deadline: Option<i64>,
+    /// The server seed, like the deck kept for replays only. Clients get it
+    /// from the hand once it is over.
+    #[serde(default)]
+    pub seed: Option<String>,
+    #[serde(default)]
+    pub seed_hash: Option<String>,
+    #[serde(default)]
+    pub client_seed: String,
+}
+
+#[Object]
+impl DealtEvent {
+    async fn table_id(&self) -> &ID {
+        &self.table_id
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        timestamp(self.created_at)
+    }
+
+    async fn players(&self) -> &[Player] {
+        &self.players
+    }
+
+    async fn config(&self) -> &TableConfig {
+        &self.config
+    }
+
+    async fn dealer_seat(&self) -> i32 {
+        self.dealer_seat
+    }
+
+    async fn straddle(&self) -> bool {
+        self.straddle
+    }
+
+    async fn action_deadline(&self) -> Option<DateTime<Utc>> {
+        self.action_deadline.map(timestamp)
+    }
+
+    async fn seed_hash(&self) -> Option<&str> {
+        self.seed_hash.as_deref()
+    }
+
+    async fn client_seed(&self) -> &str {
+        &self.client_seed
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct StreetDealtEvent {
+    pub street_type: StreetType,
+    pub cards: Vec<String>,
+    pub pot: Decimal,
+}
+
+#[Object]
+impl StreetDealtEvent {
+    async fn street_type(&self) -> StreetType {
+        self.street_type
+    }
+
+    async fn cards(&self) -> &[String] {
+        &self.cards
+    }
+
+    async fn pot(&self) -> Decimal {
+        self.pot
+    }
+}
@@ -1,42 +1,69 @@
-use async_graphql::{Context, Object, Result, ID};
+use async_graphql::{Context, ErrorExtensions, Object, Result, ID};
 use async_trait::async_trait;
-use deuces_rs::{builder::Dealer, GameDealer, RandomCardShuffler};
-use float_ord::FloatOrd;
-use mongodb::bson::to_bson;
-use mongodb::bson::{doc, Document};
-use mongodb::Database;
-use rdkafka::message::{Header, OwnedHeaders};
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use rdkafka::util::get_rdkafka_version;
+use chrono::Utc;
+use deuces_rs::{builder::Dealer, GameDealer};
 use rust_decimal::Decimal;
-use std::mem;
-use tokio::time::{timeout, Duration};
-use tonic::Request;
 use uuid::Uuid;
 
 use crate::bootstrap::schema::{
-    deal::{HandRequest, HandResponse},
-    simple_broker::SimpleBroker,
-    DealEvent,
-    DealService,
-    HandEventPayload,
-    HandToken,
-    MutationType,
-    TableToken, // Storage, TableToken,
-    UserToken,
+    auth::{signed_in, Accounts, TokenSigner},
+    engine::{
+        self, log,
+        table::{self, TableError},
+        GameEvent, GameState, PlayerCommand,
+    },
+    event_bus::{BusEvent, SharedEventBus},
+    scheduler::SharedScheduler,
+    shuffler::SharedShuffler,
+    simple_broker::Topic,
+    storage::{self, SharedHandLogRepository, SharedHandRepository, SharedTableRepository},
+    DealEvent, MutationType,
 };
 
-use super::model::{
-    ActivePlayer, Cards, DealInput, Hand, Player, PlayerAction, PlayerEvent, PlayerInput,
-    StreetEvent, StreetType,
-};
+use super::model::{DealInput, Player, PlayerAction, Seat, Table, TableConfig, TableStatus};
 
 pub struct MutationRoot;
 
 #[async_trait]
 #[cfg_attr(test, automock)]
 pub trait GameMutations {
+    async fn login(&self, ctx: &Context<'_>, user_id: ID, password: String) -> Result<String>;
+    async fn create_table(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        max_seats: Option<i32>,
+        config: Option<TableConfig>,
+    ) -> Result<Table>;
+    async fn join_table(
+        &self,
+        ctx: &Context<'_>,
+        table_id: ID,
+        seat: i32,
+        buy_in: Decimal,
+    ) -> Result<Table>;
+    async fn leave_table(&self, ctx: &Context<'_>, table_id: ID) -> Result<Seat>;
+    async fn sit_out(&self, ctx: &Context<'_>, table_id: ID) -> Result<Seat>;
+    async fn sit_in(
+        &self,
+        ctx: &Context<'_>,
+        table_id: ID,
+        wait_for_big_blind: Option<bool>,
+    ) -> Result<Seat>;
+    async fn set_client_seed(
+        &self,
+        ctx: &Context<'_>,
+        table_id: ID,
+        client_seed: String,
+    ) -> Result<Seat>;
+    async fn close_table(&self, ctx: &Context<'_>, table_id: ID) -> Result<Table>;
     async fn deal(&self, ctx: &Context<'_>, deal_input: DealInput) -> Result<ID>;
+    async fn set_table_config(
+        &self,
+        ctx: &Context<'_>,
+        table_id: ID,
+        config: TableConfig,
+    ) -> Result<TableConfig>;
     async fn play_turn(
         &self,
         ctx: &Context<'_>,
@@ -50,107 +77,191 @@ pub trait GameMutations {
 #[Object]
 #[async_trait]
 impl GameMutations for MutationRoot {
-    async fn deal(&self, ctx: &Context<'_>, deal_input: DealInput) -> Result<ID> {
-        println!("MutationRoot::deal");
-
-        // Get a handle to a collection in the database.
-
-        //let user_token = ctx.data::<UserToken>().unwrap().0.clone();
-        //let table_token = ctx.data::<TableToken>().unwrap().0.clone();
-
-        //println!("user_token: {}", user_token);
-        //println!("table_token: {}", table_token);
-
-        // let deal_client = ctx.data_unchecked::<DealService>();
-        // let req = Request::new(HandRequest {
-        //     player_count: 3 as i32,
-        // });
-
-        let shuffler = RandomCardShuffler;
-        let dealer: GameDealer<RandomCardShuffler> = GameDealer::new(shuffler);
-
-        let deal_result = dealer.deal(3); //deal_client.lock().await.deal(req).await?.into_inner();
-        let board = deal_result.board;
-        println!("Deal board: {:?}", board);
-        //let storage = ctx.data_unchecked::<Storage<String, Hand>>();
-        let db = ctx.data_unchecked::<Database>();
-        let collection = db.collection::<Document>("hands");
-
-        // let mut hands = ctx.data_unchecked::<Storage::<String, Hand>>().lock().await;
-        // println!("Deal hands: {:?}", hands);
-        // let entry = hands.vacant_entry();
-        // let id: ID = entry.key().into();
-        let id = Uuid::new_v4();
-        println!("Deal ID: {}", id.to_string());
-        //let id: ID = Uuid::new_v4().into();
-        let hand = Hand {
-            id: id.to_string().into(),
-            table_id: deal_input.table_id.into(),
-            players: deal_input
-                .players
-                .iter()
-                .enumerate()
-                .map(|(i, p)| Player {
-                    id: p.id.to_owned(),
-                    stack: p.stack,
-                    score: deal_result.hands[i].score,
-                    cards: deal_result.hands[i].cards.clone(),
-                    description: deal_result.hands[i].description.clone(),
-                })
-                .collect(),
-            cards: Cards {
-                flop: board.flop,
-                turn: board.turn,
-                river: board.river,
-            },
-            player_events: deal_input
-                .players
-                .iter()
-                .enumerate()
-                .filter(|(i, _)| [0, 1].contains(i))
-                .map(|(i, p)| PlayerEvent {
-                    amount: if i == 0 {
-                        Decimal::new(10, 0)
-                    } else {
-                        Decimal::new(20, 0)
-                    },
-                    street_type: StreetType::Preflop,
-                    action: PlayerAction::Bet,
-                    player_id: p.id.to_owned(),
-                    current_stack: if i == 0 {
-                        p.stack - Decimal::new(10, 0)
-                    } else {
-                        p.stack - Decimal::new(20, 0)
-                    },
-                    current_pot: Decimal::new(30, 0),
-                })
-                .collect(),
-            street_events: vec![StreetEvent {
-                pot: Decimal::new(30, 0),
-                current_active_players: sort_active_players(deal_input.players),
-                street_type: StreetType::Preflop,
-            }],
+    /// Signs a development account in, returning the token to send as
+    /// `x-user-token`.
+    async fn login(&self, ctx: &Context<'_>, user_id: ID, password: String) -> Result<String> {
+        println!("MutationRoot::login");
+        let accounts = ctx.data_unchecked::<Accounts>();
+        accounts
+            .check(user_id.as_str(), &password)
+            .map_err(|e| e.extend())?;
+        let tokens = ctx.data_unchecked::<TokenSigner>();
+        Ok(tokens.issue(user_id.as_str(), Utc::now().timestamp()))
+    }
+
+    /// Opens a table owned by the signed in user, with the default stakes
+    /// unless a config is given.
+    async fn create_table(
+        &self,
+        ctx: &Context<'_>,
+        name: String,
+        max_seats: Option<i32>,
+        config: Option<TableConfig>,
+    ) -> Result<Table> {
+        println!("MutationRoot::create_table");
+        let owner_id = signed_in(ctx)?;
+        let max_seats = max_seats.unwrap_or(9);
+        table::validate_max_seats(max_seats).map_err(|e| e.extend())?;
+        let config = config.unwrap_or_default();
+        config.validate()?;
+
+        let table = Table {
+            id: Uuid::new_v4().to_string().into(),
+            name,
+            owner_id,
+            version: 0,
+            created_at: Utc::now().timestamp_millis(),
+            status: TableStatus::Open,
+            max_seats,
+            config,
+            seats: vec![],
+            dealer_seat: None,
+            current_hand_id: None,
+            settled_hand_id: None,
+            // Committed to now, before anyone sits down and sets a client seed.
+            next_seed: ctx.data_unchecked::<SharedScheduler>().draw_seed(),
         };
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        tables.insert(&table).await?;
+        Ok(table)
+    }
 
-        //println!("Deal requested, full hand: {:#?}", hand);
+    /// Sits the signed in user down, to be dealt in from the next hand.
+    async fn join_table(
+        &self,
+        ctx: &Context<'_>,
+        table_id: ID,
+        seat: i32,
+        buy_in: Decimal,
+    ) -> Result<Table> {
+        println!("MutationRoot::join_table");
+        let player_id = signed_in(ctx)?;
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        let table = storage::update_table(tables, &table_id, |table| {
+            table::join(table, &player_id, seat, buy_in)
+        })
+        .await?;
+        ctx.data_unchecked::<SharedScheduler>().schedule(table_id);
+        Ok(table)
+    }
 
-        //entry.insert(hand);
-        //storage.insert(id.to_string().into(), hand).await.unwrap();
-        // Insert some documents into the "mydb.books" collection.
-        let hand_bson = to_bson(&hand)?;
+    /// Stands the signed in user up, returning the seat they left with its
+    /// final stack.
+    async fn leave_table(&self, ctx: &Context<'_>, table_id: ID) -> Result<Seat> {
+        println!("MutationRoot::leave_table");
+        let player_id = signed_in(ctx)?;
+        let hands = ctx.data_unchecked::<SharedHandRepository>();
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        let mut left = None;
+        storage::update_table_with_hand(tables, hands, &table_id, |table, current| {
+            left = Some(table::leave(table, &player_id, current)?);
+            Ok(())
+        })
+        .await?;
+        left.ok_or_else(|| TableError::NotSeated.extend())
+    }
 
-        match hand_bson {
-            mongodb::bson::Bson::Document(document) => {
-                collection.insert_one(document, None).await?;
-            }
-            _ => return Err("Error converting hand to BSON document".into()),
-        }
+    /// Keeps the signed in user's seat but stops dealing them in, from the
+    /// next hand. Blinds that pass them meanwhile are owed when they return.
+    async fn sit_out(&self, ctx: &Context<'_>, table_id: ID) -> Result<Seat> {
+        println!("MutationRoot::sit_out");
+        let player_id = signed_in(ctx)?;
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        let mut seat = None;
+        storage::update_table(tables, &table_id, |table| {
+            seat = Some(table::sit_out(table, &player_id)?);
+            Ok(())
+        })
+        .await?;
+        seat.ok_or_else(|| TableError::NotSeated.extend())
+    }
 
-        SimpleBroker::publish(DealEvent {
-            mutation_type: MutationType::Created,
-            id: id.to_string().into(),
-        });
-        Ok(id.to_string().into())
+    /// Deals the signed in user back in. Missed blinds are posted with their
+    /// next hand, or they can wait for the big blind to reach them instead.
+    async fn sit_in(
+        &self,
+        ctx: &Context<'_>,
+        table_id: ID,
+        wait_for_big_blind: Option<bool>,
+    ) -> Result<Seat> {
+        println!("MutationRoot::sit_in");
+        let player_id = signed_in(ctx)?;
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        let wait = wait_for_big_blind.unwrap_or(false);
+        let mut seat = None;
+        storage::update_table(tables, &table_id, |table| {
+            seat = Some(table::sit_in(table, &player_id, wait)?);
+            Ok(())
+        })
+        .await?;
+        ctx.data_unchecked::<SharedScheduler>().schedule(table_id);
+        seat.ok_or_else(|| TableError::NotSeated.extend())
+    }
+
+    /// Sets a seed of the signed in user's choosing to mix into the shuffle
+    /// of the hands they are dealt. The server seed it is mixed with is
+    /// committed to before then, so the server can't choose the deck alone.
+    async fn set_client_seed(
+        &self,
+        ctx: &Context<'_>,
+        table_id: ID,
+        client_seed: String,
+    ) -> Result<Seat> {
+        println!("MutationRoot::set_client_seed");
+        let player_id = signed_in(ctx)?;
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        let mut seat = None;
+        storage::update_table(tables, &table_id, |table| {
+            seat = Some(table::set_client_seed(table, &player_id, &client_seed)?);
+            Ok(())
+        })
+        .await?;
+        seat.ok_or_else(|| TableError::NotSeated.extend())
+    }
+
+    /// Stops the table dealing once any hand in progress is finished.
+    async fn close_table(&self, ctx: &Context<'_>, table_id: ID) -> Result<Table> {
+        println!("MutationRoot::close_table");
+        let user_id = signed_in(ctx)?;
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        storage::update_table(tables, &table_id, |table| {
+            table::authorize_owner(table, &user_id)?;
+            table::close(table);
+            Ok(())
+        })
+        .await
+    }
+
+    /// Deals the next hand straight away, without waiting for the countdown.
+    async fn deal(&self, ctx: &Context<'_>, deal_input: DealInput) -> Result<ID> {
+        let user_id = signed_in(ctx)?;
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        let table = storage::load_table(tables, &deal_input.table_id).await?;
+        table::authorize_owner(&table, &user_id).map_err(|e| e.extend())?;
+
+        let scheduler = ctx.data_unchecked::<SharedScheduler>();
+        scheduler.deal(deal_input).await
+    }
+
+    /// Changes the table's stakes from its next hand.
+    async fn set_table_config(
+        &self,
+        ctx: &Context<'_>,
+        table_id: ID,
+        config: TableConfig,
+    ) -> Result<TableConfig> {
+        println!("MutationRoot::set_table_config");
+        config.validate()?;
+        let user_id = signed_in(ctx)?;
+
+        let tables = ctx.data_unchecked::<SharedTableRepository>();
+        storage::update_table(tables, &table_id, |table| {
+            table::authorize_owner(table, &user_id)?;
+            table.config = config.clone();
+            Ok(())
+        })
+        .await?;
+        Ok(config)
     }
 
     async fn play_turn(
@@ -162,295 +273,879 @@ impl GameMutations for MutationRoot {
         amount: Decimal,
     ) -> Result<ID> {
         println!("MutationRoot::play_turn");
+        let user_id = signed_in(ctx)?;
+        engine::authorize(Some(user_id.as_str()), &player_id).map_err(|e| e.extend())?;
 
-        let user_token = ctx.data::<UserToken>().unwrap().0.clone();
-        let hand_token = ctx.data::<HandToken>().unwrap().0.clone();
-        println!("user_token: {}", user_token);
-        println!("hand_token: {}", hand_token);
-
-        let producer = ctx.data_unchecked::<FutureProducer>();
-        let db = ctx.data_unchecked::<Database>();
-        let typed_collection = db.collection::<Hand>("hands");
-        let hand_option = typed_collection
-            .find_one(doc! { "id": hand_token }, None)
+        let scheduler = ctx.data_unchecked::<SharedScheduler>();
+        scheduler
+            .play(
+                &id,
+                PlayerCommand {
+                    player_id,
+                    action,
+                    amount,
+                },
+                false,
+            )
             .await?;
-        let mut hand =
-            hand_option.ok_or_else(|| "No document found with the specified id".to_string())?;
+        Ok(id)
+    }
+}
 
-        // get last player event:
-        let last_player_event = hand.player_events.last().unwrap();
+/// Deals the next hand to the table's seated players who are not sitting
+/// out, moving the button on from the last hand. The deck is shuffled by
+/// `shuffler` from the seed the table committed to before the hand, and
+/// the hand keeps it. The seed for the hand after is drawn in its place.
+pub async fn deal_hand(
+    hands: &SharedHandRepository,
+    hand_log: &SharedHandLogRepository,
+    tables: &SharedTableRepository,
+    bus: &SharedEventBus,
+    shuffler: &SharedShuffler,
+    deal_input: DealInput,
+) -> Result<ID> {
+    let table_id = deal_input.table_id.clone();
+    let table = storage::load_table(tables, &table_id).await?;
+    let current = storage::current_hand(hands, &table).await?;
+    let (seats, dealer_seat) =
+        table::deal_in(&table, current.as_ref()).map_err(|e| e.extend())?;
 
-        // build current player event
-        let player = hand
-            .players
-            .iter_mut()
-            .find(|p| p.id == player_id)
-            .ok_or("Player not found")?;
-        player.stack -= amount;
-
-        let current_player_event = PlayerEvent {
-            amount: amount,
-            street_type: last_player_event.street_type,
-            action: action,
-            player_id: player_id.clone(),
-            current_stack: player.stack,
-            current_pot: last_player_event.current_pot + amount,
-        };
+    let client_seed = table::client_seed(&seats);
+    // Tables from before seeds were committed to have none yet.
+    let seed = table.next_seed.clone().or_else(|| shuffler.draw_seed());
+    let deck = shuffler.shuffle(seed.as_deref(), &client_seed)?;
+    let (seed, seed_hash) = (deck.seed.clone(), deck.commitment());
+    // The board comes off the deck after the hole cards, a street at a time
+    // with a card burned before each.
+    let rest: Vec<String> = deck.cards[seats.len() * 2..]
+        .iter()
+        .map(|card| card.to_string())
+        .collect();
+    let dealer = GameDealer::new(deck);
 
-        hand.player_events.push(current_player_event);
+    let deal_result = dealer.deal(seats.len());
 
-        println!("current hand: {:#?}", hand);
+    let id = Uuid::new_v4();
+    println!("Deal ID: {}", id);
+    let players: Vec<Player> = seats
+        .iter()
+        .enumerate()
+        .map(|(i, s)| Player {
+            id: s.player_id.clone(),
+            seat: s.seat,
+            stack: s.stack,
+            // Ranked at showdown, against the board as it is dealt.
+            score: 0.0,
+            cards: deal_result.hands[i].cards.clone(),
+            description: String::new(),
+            best_cards: vec![],
+            shown: false,
+            missed_small_blind: s.missed_small_blind,
+            missed_big_blind: s.missed_big_blind,
+        })
+        .collect();
+    let straddle = deal_input.straddle.unwrap_or(false);
+    let mut hand = engine::start_hand(
+        id.to_string().into(),
+        table_id.clone(),
+        players,
+        rest,
+        table.config.clone(),
+        dealer_seat,
+        straddle,
+    );
 
-        // Update active players on hand from last street event, set inactive if player event is fold:
-        let mut active_players = hand
-            .street_events
-            .last()
-            .unwrap()
-            .current_active_players
-            .clone();
+    hand.created_at = Utc::now().timestamp_millis();
+    hand.seed = seed;
+    hand.seed_hash = seed_hash.clone();
+    hand.client_seed = client_seed.clone();
+    hand.action_deadline = engine::action_deadline(&hand, hand.created_at);
 
-        println!("active_players pre fold: {:#?}", active_players);
+    // Claim the table first, so two deals can't both start a hand on it.
+    let mut claimed = table.clone();
+    table::start(&mut claimed, &hand);
+    claimed.next_seed = shuffler.draw_seed();
+    claimed.version = table.version + 1;
+    tables
+        .save(&claimed, table.version)
+        .await
+        .map_err(|e| e.extend())?;
 
-        let last_street_event: &mut StreetEvent = hand.street_events.iter_mut().last().unwrap();
+    // The hand is dealt once it is stored. If that fails the claim points at
+    // no hand, which the next deal goes ahead over.
+    let dealt = log::dealt(&hand, straddle);
+    let entries = log::record(&mut hand, dealt);
+    hands.insert(&hand).await?;
 
-        if action == PlayerAction::Bet {
-            last_street_event.pot += amount;
+    // Nothing after this undoes the deal, so failures are only logged.
+    if let Err(e) = hand_log.append(&entries).await {
+        println!("Error logging the deal of hand {}: {:?}", id, e);
+    }
+    let deal_event = DealEvent {
+        mutation_type: MutationType::Created,
+        id: id.to_string().into(),
+        seed_hash,
+        client_seed,
+    };
+    let topics = hand
+        .players
+        .iter()
+        .map(|p| Topic::User(p.id.to_string()))
+        .chain([Topic::Table(table_id.to_string())]);
+    for topic in topics {
+        if let Err(e) = bus.publish(topic, BusEvent::Deal(deal_event.clone())).await {
+            println!("Error publishing the deal of hand {}: {:?}", id, e);
         }
+    }
+    Ok(id.to_string().into())
+}
+
+/// A command applied and saved by [`play_command`].
+pub struct PlayedCommand {
+    /// The hand as it was saved.
+    pub state: GameState,
+    pub events: Vec<GameEvent>,
+    /// How many milliseconds the player took past their deadline.
+    pub overtime: i64,
+    /// The sequence of the last log entry written.
+    pub sequence: i64,
+}
+
+/// Applies a command to the stored hand and saves the result, failing with a
+/// retryable `CONFLICT` if another action was saved first. What happened is
+/// then appended to the hand's log, numbered from the sequence the save
+/// claimed.
+///
+/// `now` is when the command arrived, in milliseconds since the Unix epoch.
+/// The next player's clock starts from it.
+pub async fn play_command(
+    hands: &SharedHandRepository,
+    hand_log: &SharedHandLogRepository,
+    id: &ID,
+    command: PlayerCommand,
+    now: i64,
+) -> Result<PlayedCommand> {
+    let hand = storage::load_hand(hands, id).await?;
+    let expected_version = hand.version;
+    let overtime = hand.action_deadline.map_or(0, |deadline| (now - deadline).max(0));
+
+    let mut state = GameState::new(hand);
+    let events = state.apply(command).map_err(|e| e.extend())?;
+
+    let mut hand = state.into_hand();
+    hand.version = expected_version + 1;
+    hand.action_deadline = engine::action_deadline(&hand, now);
+    let acted = log::acted(&events, &hand);
+    let entries = log::record(&mut hand, acted);
+    hands
+        .save(&hand, expected_version)
+        .await
+        .map_err(|e| e.extend())?;
+    hand_log.append(&entries).await?;
+    let sequence = hand.log_sequence;
+    Ok(PlayedCommand {
+        state: GameState::new(hand),
+        events,
+        overtime,
+        sequence,
+    })
+}
 
-        let current_pot = last_street_event.pot;
+#[cfg(test)]
+use mockall::automock;
 
-        let mut cap = active_players.iter_mut();
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
 
-        let active_player_count = cap.filter(|p| !p.is_inactive).count();
+    use async_graphql::Value;
+    use futures::StreamExt;
 
-        let current_active_player = active_players
-            .iter_mut()
-            .find(|p| p.id == player_id)
-            .ok_or("Player not found")?;
-        current_active_player.bet += amount;
-        let current_bet = current_active_player.bet;
+    use super::*;
+    use crate::bootstrap::schema::model::{fixtures, Cards, Hand, HandLogEntry, HandStatus};
+    use crate::bootstrap::schema::event_bus::{EventBus, InMemoryEventBus};
+    use crate::bootstrap::schema::scheduler::Scheduler;
+    use crate::bootstrap::schema::shuffler::{self, SeededShuffler, StackedDeck};
+    use crate::bootstrap::schema::storage::memory::{
+        InMemoryHandLogRepository, InMemoryHandRepository, InMemoryTableRepository,
+    };
+    use crate::bootstrap::schema::{
+        HandEventPayload, HandToken, PokerSchema, QueryRoot, SubscriptionRoot, UserToken,
+    };
+    use crate::bootstrap::schema::storage::{
+        HandCursor, HandFilter, HandLogRepository, HandRepository, StorageError,
+        TableRepository,
+    };
+
+    /// Pauses after each read so concurrent commands race on the same version.
+    struct SlowReads(InMemoryHandRepository);
 
-        println!("active_player_count pre fold: {}", active_player_count);
+    #[async_trait]
+    impl HandRepository for SlowReads {
+        async fn insert(&self, hand: &Hand) -> Result<()> {
+            self.0.insert(hand).await
+        }
 
-        if action == PlayerAction::Fold {
-            current_active_player.is_inactive = true;
+        async fn find(&self, id: &ID) -> Result<Option<Hand>> {
+            let hand = self.0.find(id).await;
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+            hand
         }
 
-        let next_active_player_count = active_players.iter_mut().filter(|p| !p.is_inactive).count();
-        println!(
-            "active_player_count after fold: {}",
-            next_active_player_count
+        async fn list(
+            &self,
+            filter: &HandFilter,
+            after: Option<&HandCursor>,
+            limit: i64,
+        ) -> Result<Vec<Hand>> {
+            self.0.list(filter, after, limit).await
+        }
+
+        async fn save(&self, hand: &Hand, expected_version: i64) -> Result<(), StorageError> {
+            self.0.save(hand, expected_version).await
+        }
+    }
+
+    fn code(error: &async_graphql::Error) -> Option<Value> {
+        error
+            .extensions
+            .as_ref()
+            .and_then(|extensions| extensions.get("code").cloned())
+    }
+
+    async fn seed_hand(hands: &SharedHandRepository, hand_log: &SharedHandLogRepository) -> ID {
+        let players = [("sb", ["4s", "5h"]), ("bb", ["Ah", "Ad"]), ("btn", ["Qd", "Qc"])]
+            .iter()
+            .enumerate()
+            .map(|(i, (id, cards))| Player {
+                id: (*id).into(),
+                seat: i as i32,
+                stack: Decimal::new(1000, 0),
+                cards: cards.iter().map(|card| card.to_string()).collect(),
+                score: 0.0,
+                description: String::new(),
+                best_cards: vec![],
+                shown: false,
+                missed_small_blind: false,
+                missed_big_blind: false,
+            })
+            .collect();
+        let deck = ["6s", "2c", "7d", "9h", "6h", "Js", "6d", "Kc"]
+            .iter()
+            .map(|card| card.to_string())
+            .collect();
+        let mut hand = engine::start_hand(
+            "hand".into(),
+            "table".into(),
+            players,
+            deck,
+            TableConfig::default(),
+            2,
+            false,
         );
+        let dealt = log::dealt(&hand, false);
+        let entries = log::record(&mut hand, dealt);
+        hands.insert(&hand).await.unwrap();
+        hand_log.append(&entries).await.unwrap();
+        hand.id
+    }
+
+    /// Holds back the first append after `hold` is set, so a later writer's
+    /// entries land before it.
+    #[derive(Default)]
+    struct HeldAppends {
+        log: InMemoryHandLogRepository,
+        hold: AtomicBool,
+    }
+
+    #[async_trait]
+    impl HandLogRepository for HeldAppends {
+        async fn find(&self, hand_id: &ID) -> Result<Vec<HandLogEntry>> {
+            self.log.find(hand_id).await
+        }
 
-        let all_bets_equal = active_players.iter_mut().all(|p| p.bet == current_bet);
+        async fn append(&self, entries: &[HandLogEntry]) -> Result<()> {
+            if self.hold.swap(false, Ordering::SeqCst) {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            self.log.append(entries).await
+        }
+    }
 
-        let is_last_active_player = active_players.iter_mut().last().unwrap().id == player_id;
+    /// Reads the table as it was before the last deal once, as a reader
+    /// racing the deal would.
+    struct StaleRead {
+        tables: InMemoryTableRepository,
+        stale: std::sync::Mutex<Option<Table>>,
+    }
 
-        let should_change_street = all_bets_equal && is_last_active_player;
+    #[async_trait]
+    impl TableRepository for StaleRead {
+        async fn insert(&self, table: &Table) -> Result<()> {
+            self.tables.insert(table).await
+        }
 
-        // what's the next street?
-        let next_street_type = if should_change_street {
-            match last_street_event.street_type {
-                StreetType::Preflop => StreetType::Flop,
-                StreetType::Flop => StreetType::Turn,
-                StreetType::Turn => StreetType::River,
-                StreetType::River => StreetType::Preflop,
+        async fn find(&self, id: &ID) -> Result<Option<Table>> {
+            let stale = self.stale.lock().unwrap().take();
+            match stale {
+                Some(table) => Ok(Some(table)),
+                None => self.tables.find(id).await,
             }
+        }
+
+        async fn list(&self, status: TableStatus) -> Result<Vec<Table>> {
+            self.tables.list(status).await
+        }
+
+        async fn save(&self, table: &Table, expected_version: i64) -> Result<(), StorageError> {
+            self.tables.save(table, expected_version).await
+        }
+    }
+
+    /// A log and a bus that are both down.
+    struct Outage;
+
+    #[async_trait]
+    impl HandLogRepository for Outage {
+        async fn find(&self, _hand_id: &ID) -> Result<Vec<HandLogEntry>> {
+            Err("log is down".into())
+        }
+
+        async fn append(&self, _entries: &[HandLogEntry]) -> Result<()> {
+            Err("log is down".into())
+        }
+    }
+
+    #[async_trait]
+    impl EventBus for Outage {
+        async fn publish(&self, _topic: Topic, _event: BusEvent) -> Result<()> {
+            Err("bus is down".into())
+        }
+
+        fn subscribe(&self, _topic: Topic) -> futures::stream::BoxStream<'static, BusEvent> {
+            futures::stream::empty().boxed()
+        }
+    }
+
+    /// Checks or calls for whoever is to act.
+    async fn next_command(hands: &SharedHandRepository, id: &ID) -> Option<PlayerCommand> {
+        let hand = storage::load_hand(hands, id).await.unwrap();
+        let player_id = hand.to_act.clone()?;
+        let street = hand.street_events.last()?;
+        let current_bet = street
+            .current_active_players
+            .iter()
+            .map(|p| p.bet)
+            .max()
+            .unwrap_or_default();
+        let bet = street
+            .current_active_players
+            .iter()
+            .find(|p| p.id == player_id)?
+            .bet;
+        let (action, amount) = if bet < current_bet {
+            (PlayerAction::Call, current_bet - bet)
         } else {
-            last_street_event.street_type
+            (PlayerAction::Check, Decimal::ZERO)
         };
+        Some(PlayerCommand {
+            player_id,
+            action,
+            amount,
+        })
+    }
 
-        let game_over = next_active_player_count == 1
-            || should_change_street && next_street_type == StreetType::Preflop;
-        println!("game_over: {}", game_over);
-        if game_over {
-            // who won? e.g. who gets the pot?
-            let mut winner_player_id: String = "TODO: find winner".to_string();
-            if active_player_count == 1 {
-                let winner = active_players.iter().find(|p| !p.is_inactive).unwrap();
-                winner_player_id = winner.id.clone().to_string();
-            } else {
-                // find player in hand with the highest score:
-                let winner_player = active_players
-                    .iter()
-                    .filter(|p| !p.is_inactive)
-                    .max_by_key(|p| {
-                        let player = hand.players.iter().find(|l| l.id == p.id).unwrap();
-                        FloatOrd(player.score)
-                    })
-                    .ok_or("No winner found")?;
-
-                winner_player_id = winner_player.id.clone().to_string();
-            }
-            let mut next_players = hand.players.clone();
-
-            // map active_players stack to next_players stacks:
-            for player in next_players.iter_mut() {
-                let most_recent_player_event =
-                    hand.player_events.iter().find(|p| p.player_id == player.id);
-                // TODO: handle player not found
-                player.stack = most_recent_player_event.unwrap().current_stack;
-                if player.id.to_string() == winner_player_id {
-                    player.stack += current_pot;
-                }
+    #[tokio::test]
+    async fn stale_command_is_a_retryable_conflict() {
+        let hands: SharedHandRepository = Arc::new(InMemoryHandRepository::default());
+        let hand_log: SharedHandLogRepository = Arc::new(InMemoryHandLogRepository::default());
+        let id = seed_hand(&hands, &hand_log).await;
+        let stale = storage::load_hand(&hands, &id).await.unwrap();
+
+        let command = next_command(&hands, &id).await.unwrap();
+        play_command(&hands, &hand_log, &id, command.clone(), 0)
+            .await
+            .unwrap();
+
+        // Replaying the same command against the old version must not save.
+        let mut state = GameState::new(stale.clone());
+        state.apply(command).unwrap();
+        let mut hand = state.into_hand();
+        hand.version = stale.version + 1;
+        let err = hands
+            .save(&hand, stale.version)
+            .await
+            .map_err(|e| e.extend())
+            .unwrap_err();
+        assert_eq!(code(&err), Some(Value::from("CONFLICT")));
+        assert_eq!(
+            err.extensions.as_ref().and_then(|e| e.get("retryable").cloned()),
+            Some(Value::from(true))
+        );
+    }
+
+    #[tokio::test]
+    async fn interleaved_appends_keep_their_own_sequences() {
+        let hands: SharedHandRepository =
+            Arc::new(SlowReads(InMemoryHandRepository::default()));
+        let held = Arc::new(HeldAppends::default());
+        let hand_log: SharedHandLogRepository = held.clone();
+        let id = seed_hand(&hands, &hand_log).await;
+
+        // The first command is saved, but its log entries are held back
+        // while the next command is saved and logged.
+        held.hold.store(true, Ordering::SeqCst);
+        let first = next_command(&hands, &id).await.unwrap();
+        let second = async {
+            while storage::load_hand(&hands, &id).await.unwrap().version == 0 {
+                tokio::task::yield_now().await;
             }
+            let command = next_command(&hands, &id).await.unwrap();
+            play_command(&hands, &hand_log, &id, command, 0).await
+        };
+        let (first, second) = tokio::join!(
+            play_command(&hands, &hand_log, &id, first, 0),
+            second
+        );
+        let first = first.unwrap().sequence;
+        let second = second.unwrap().sequence;
+        assert!(first < second);
 
-            let last_player = next_players.remove(0);
-            next_players.push(last_player);
+        let mut hand = storage::load_hand(&hands, &id).await.unwrap();
+        let entries = hand_log.find(&id).await.unwrap();
+        let sequences: Vec<i64> = entries.iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, (1..=hand.log_sequence).collect::<Vec<_>>());
+        // When each action arrived isn't logged, so neither is the deadline.
+        hand.action_deadline = None;
+        assert_eq!(log::replay(&entries), Ok(hand));
+    }
 
-            let deal_input = DealInput {
-                table_id: hand.table_id.clone().into(),
-                players: next_players
-                    .iter()
-                    .map(|p| PlayerInput {
-                        id: p.id.clone().into(),
-                        stack: p.stack,
-                    })
-                    .collect(),
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_commands_lose_no_player_events() {
+        let hands: SharedHandRepository =
+            Arc::new(SlowReads(InMemoryHandRepository::default()));
+        let hand_log: SharedHandLogRepository = Arc::new(InMemoryHandLogRepository::default());
+        let id = seed_hand(&hands, &hand_log).await;
+        let applied = Arc::new(AtomicUsize::new(0));
+        let conflicts = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let hands = hands.clone();
+                let hand_log = hand_log.clone();
+                let id = id.clone();
+                let applied = applied.clone();
+                let conflicts = conflicts.clone();
+                tokio::spawn(async move {
+                    while let Some(command) = next_command(&hands, &id).await {
+                        match play_command(&hands, &hand_log, &id, command, 0).await {
+                            Ok(_) => {
+                                applied.fetch_add(1, Ordering::SeqCst);
+                            }
+                            Err(e) if code(&e) == Some(Value::from("CONFLICT")) => {
+                                conflicts.fetch_add(1, Ordering::SeqCst);
+                            }
+                            // Someone else acted first and the turn moved on.
+                            Err(e) if code(&e) == Some(Value::from("OUT_OF_TURN")) => {}
+                            Err(e) if code(&e) == Some(Value::from("HAND_COMPLETE")) => {}
+                            Err(e) => panic!("unexpected error {:?}", e),
+                        }
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let hand = storage::load_hand(&hands, &id).await.unwrap();
+        let applied = applied.load(Ordering::SeqCst);
+        assert_eq!(hand.status, HandStatus::Complete);
+        // Two blinds, then one event per successful command.
+        assert_eq!(hand.player_events.len(), 2 + applied);
+        assert_eq!(hand.version as usize, applied);
+        assert!(conflicts.load(Ordering::SeqCst) > 0);
+
+        // The log holds exactly the accepted actions, so it replays to the same hand.
+        let entries = hand_log.find(&id).await.unwrap();
+        assert_eq!(log::replay(&entries), Ok(hand));
+    }
+
+    async fn execute(schema: &PokerSchema, user: &str, query: String) -> Value {
+        let request = async_graphql::Request::new(query).data(UserToken(user.to_string()));
+        let response = schema.execute(request).await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        response.data
+    }
+
+    #[tokio::test]
+    async fn deals_stand_once_the_hand_is_stored() {
+        let hands: SharedHandRepository = Arc::new(InMemoryHandRepository::default());
+        let hand_log: SharedHandLogRepository = Arc::new(Outage);
+        let tables: SharedTableRepository = Arc::new(InMemoryTableRepository::default());
+        let bus: SharedEventBus = Arc::new(Outage);
+        let shuffler: SharedShuffler = Arc::new(SeededShuffler::new(None));
+        let mut table = fixtures::table("outage");
+        for (seat, player_id) in ["ann", "bob"].iter().enumerate() {
+            table::join(&mut table, &(*player_id).into(), seat as i32, Decimal::new(1000, 0))
+                .unwrap();
+        }
+        tables.insert(&table).await.unwrap();
+
+        // Neither the log nor the subscribers hearing of it undoes the deal.
+        let deal_input = DealInput {
+            table_id: table.id.clone(),
+            straddle: None,
+        };
+        let id = deal_hand(&hands, &hand_log, &tables, &bus, &shuffler, deal_input)
+            .await
+            .unwrap();
+        let claimed = storage::load_table(&tables, &table.id).await.unwrap();
+        assert_eq!(claimed.current_hand_id, Some(id.clone()));
+        assert!(storage::load_hand(&hands, &id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn players_in_a_hand_dealt_meanwhile_cannot_leave() {
+        let hands: SharedHandRepository = Arc::new(InMemoryHandRepository::default());
+        let hand_log: SharedHandLogRepository = Arc::new(InMemoryHandLogRepository::default());
+        let stale_read = Arc::new(StaleRead {
+            tables: InMemoryTableRepository::default(),
+            stale: Default::default(),
+        });
+        let tables: SharedTableRepository = stale_read.clone();
+        let bus: SharedEventBus = Arc::new(InMemoryEventBus);
+        let shuffler: SharedShuffler = Arc::new(SeededShuffler::new(None));
+        let mut table = fixtures::table("race");
+        for (seat, player_id) in ["ann", "bob"].iter().enumerate() {
+            table::join(&mut table, &(*player_id).into(), seat as i32, Decimal::new(1000, 0))
+                .unwrap();
+        }
+        tables.insert(&table).await.unwrap();
+        let deal_input = DealInput {
+            table_id: table.id.clone(),
+            straddle: None,
+        };
+        deal_hand(&hands, &hand_log, &tables, &bus, &shuffler, deal_input)
+            .await
+            .unwrap();
+
+        // Ann's first read of the table is from before the deal.
+        *stale_read.stale.lock().unwrap() = Some(table);
+        let schema = async_graphql::Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+            .data(hands)
+            .data(tables)
+            .finish();
+        let leave = r#"mutation { leaveTable(tableId: "race") { seat } }"#;
+        let request = async_graphql::Request::new(leave).data(UserToken("ann".to_string()));
+        let response = schema.execute(request).await;
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .as_ref()
+                .and_then(|e| e.get("code").cloned()),
+            Some(Value::from("IN_HAND"))
+        );
+    }
+
+    #[tokio::test]
+    async fn deals_one_hand_to_each_of_two_to_ten_players() {
+        let hands: SharedHandRepository = Arc::new(InMemoryHandRepository::default());
+        let hand_log: SharedHandLogRepository = Arc::new(InMemoryHandLogRepository::default());
+        let tables: SharedTableRepository = Arc::new(InMemoryTableRepository::default());
+        let bus: SharedEventBus = Arc::new(InMemoryEventBus);
+        let shuffler: SharedShuffler = Arc::new(SeededShuffler::new(Some(23)));
+
+        for players in 2..=table::MAX_SEATS {
+            let mut table = Table {
+                max_seats: table::MAX_SEATS,
+                next_seed: shuffler.draw_seed(),
+                ..fixtures::table(&format!("matrix-{}", players))
             };
-            println!("before storage");
-            let hand_bson = to_bson(&hand)?;
-            match hand_bson {
-                mongodb::bson::Bson::Document(document) => {
-                    let update = doc! { "$set": document };
-                    let upsert_result = typed_collection
-                        .find_one_and_update(doc! {"id": id.to_string()}, update, None)
-                        .await?;
-                    println!("Updated document with ID: {:?}", upsert_result);
-                }
-                _ => return Err("Error converting hand to BSON document".into()),
+            for seat in 0..players {
+                let player_id = format!("p{}", seat).into();
+                table::join(&mut table, &player_id, seat, Decimal::new(1000, 0)).unwrap();
             }
-            // storage.insert(hand_token, hand).await.unwrap();
-            println!("Starting deal function");
-            let result = self.deal(ctx, deal_input).await;
-            println!("how to print line {:?}", result);
-            println!("Finished deal function");
-        } else {
-            println!("not game over block");
-            // move current_active_player to end of active_players array:
-            let current_active_player = active_players.remove(0);
-            active_players.push(current_active_player);
-
-            // TODO: rotate to next active player
-            println!("build next street event");
-            let next_street_event = StreetEvent {
-                pot: last_street_event.pot,
-                current_active_players: active_players,
-                street_type: next_street_type,
-                //TODO: allowed actions
-            };
-            println!("push next street event");
-            hand.street_events.push(next_street_event.clone());
-            println!("build payload");
-            let payload = HandEventPayload {
-                mutation_type: MutationType::Updated,
-                hand_id: id.clone(),
-                street_event: Some(next_street_event),
-                player_event: Some(hand.player_events.last().unwrap().clone()),
-                cards: None, //TODO: cards
+            tables.insert(&table).await.unwrap();
+
+            let deal_input = DealInput {
+                table_id: table.id.clone(),
+                straddle: None,
             };
-            println!("before storage");
-            let hand_bson = to_bson(&hand)?;
-            match hand_bson {
-                mongodb::bson::Bson::Document(document) => {
-                    let update = doc! { "$set": document };
-                    let upsert_result = typed_collection
-                        .find_one_and_update(doc! {"id": id.to_string()}, update, None)
-                        .await?;
-                    println!("Updated document with ID: {:?}", upsert_result);
-                }
-                _ => return Err("Error converting hand to BSON document".into()),
-            }
+            let id = deal_hand(&hands, &hand_log, &tables, &bus, &shuffler, deal_input)
+                .await
+                .unwrap();
+            let mut hand = storage::load_hand(&hands, &id).await.unwrap();
+            assert_eq!(hand.players.len(), players as usize);
+            // Shuffled from the seed the table committed to before the deal,
+            // with a fresh one committed to for the next hand.
+            assert_eq!(hand.seed, table.next_seed);
+            let dealt_at = storage::load_table(&tables, &table.id).await.unwrap();
+            assert!(dealt_at.next_seed.is_some());
+            assert_ne!(dealt_at.next_seed, hand.seed);
+            // The log deals the same hand again, seeds and commitment included.
+            let entries = hand_log.find(&id).await.unwrap();
+            assert!(hand.seed.is_some());
+            assert_eq!(log::replay(&entries), Ok(hand.clone()));
+            let mut cards: Vec<&String> = hand
+                .players
+                .iter()
+                .flat_map(|p| p.cards.iter())
+                .chain(hand.deck.iter())
+                .collect();
+            cards.sort();
+            cards.dedup();
+            assert_eq!(cards.len(), 52);
+            assert_eq!(hand.cards, Cards::default());
 
-            let topic = "loony_topic";
-            let hand_id = &hand.id.as_str();
-
-            //serde_json::to_string(&hand).unwrap();
-
-            let futures = (0..5)
-                .map(|i| async move {
-                    // The send operation on the topic returns a future, which will be
-                    // completed once the result or failure from Kafka is received.
-                    let delivery_status = producer
-                        .send(
-                            FutureRecord::to(topic)
-                                .payload(&format!("Message {}", i))
-                                .key(&format!("Key {}", i))
-                                .headers(OwnedHeaders::new().insert(Header {
-                                    key: "header_key",
-                                    value: Some("header_value"),
-                                })),
-                            Duration::from_secs(0),
-                        )
-                        .await;
-
-                    // This will be executed when the result is received.
-                    // println("Delivery status for message {} received", i);
-                    delivery_status
-                })
-                .collect::<Vec<_>>();
+            hand.status = HandStatus::Complete;
+            assert!(shuffler::verify(&hand).unwrap().cards_match);
+        }
 
-            // This loop will wait until all delivery statuses have been received.
-            for future in futures {
-                println!("Future completed. Result: {:?}", future.await);
-                //info!("Future completed. Result: {:?}", future.await);
-            }
-            SimpleBroker::publish(payload.clone());
+        // Outside two to the table's seats the deal is refused.
+        let mut table = storage::load_table(&tables, &"matrix-3".into()).await.unwrap();
+        table.current_hand_id = None;
+        table.max_seats = 2;
+        tables.save(&table, table.version).await.unwrap();
+        for (seats, expected) in [(3, "TOO_MANY_PLAYERS"), (1, "NOT_ENOUGH_PLAYERS")] {
+            let mut table = storage::load_table(&tables, &"matrix-3".into()).await.unwrap();
+            table.seats.truncate(seats);
+            tables.save(&table, table.version).await.unwrap();
+            let deal_input = DealInput {
+                table_id: table.id.clone(),
+                straddle: None,
+            };
+            let err = deal_hand(&hands, &hand_log, &tables, &bus, &shuffler, deal_input)
+                .await
+                .unwrap_err();
+            assert_eq!(code(&err), Some(Value::from(expected)));
         }
+    }
+
+    #[tokio::test]
+    async fn development_accounts_log_in_for_a_token() {
+        let tokens = TokenSigner::new(b"secret");
+        let schema = async_graphql::Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+            .data(tokens.clone())
+            .data(Accounts::parse("ann:pw"))
+            .finish();
+
+        let response = schema
+            .execute(r#"mutation { login(userId: "ann", password: "pw") }"#)
+            .await;
+        let token = response.data.into_json().unwrap()["login"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            tokens.verify(&token, Utc::now().timestamp()),
+            Ok("ann".into())
+        );
+
+        let response = schema
+            .execute(r#"mutation { login(userId: "ann", password: "nope") }"#)
+            .await;
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .as_ref()
+                .and_then(|e| e.get("code").cloned()),
+            Some(Value::from("INVALID_CREDENTIALS"))
+        );
 
-        Ok(id.clone())
+        // Anything needing a user says so when nobody is signed in.
+        let response = schema
+            .execute(r#"mutation { createTable(name: "Main") { id } }"#)
+            .await;
+        assert_eq!(
+            response.errors[0]
+                .extensions
+                .as_ref()
+                .and_then(|e| e.get("code").cloned()),
+            Some(Value::from("UNAUTHENTICATED"))
+        );
     }
-}
 
-fn build_active_players(players: Vec<PlayerInput>) -> Vec<ActivePlayer> {
-    players
-        .iter()
-        .enumerate()
-        .map(|(i, p)| match i {
-            0 => ActivePlayer {
-                id: p.id.clone(),
-                bet: Decimal::new(10, 0),
-                stack: p.stack - Decimal::new(10, 0),
-                is_inactive: false,
-                is_big_blind: false,
-            },
-            1 => ActivePlayer {
-                id: p.id.clone(),
-                bet: Decimal::new(20, 0),
-                stack: p.stack - Decimal::new(20, 0),
-                is_inactive: false,
-                is_big_blind: true,
-            },
-            _ => ActivePlayer {
-                id: p.id.clone(),
-                bet: Decimal::new(0, 0),
-                stack: p.stack,
-                is_inactive: false,
-                is_big_blind: false,
-            },
-        })
-        .collect()
-}
+    #[tokio::test]
+    async fn tables_deal_to_their_seated_players() {
+        let hands: SharedHandRepository = Arc::new(InMemoryHandRepository::default());
+        let hand_log: SharedHandLogRepository = Arc::new(InMemoryHandLogRepository::default());
+        let tables: SharedTableRepository = Arc::new(InMemoryTableRepository::default());
+        let bus: SharedEventBus = Arc::new(InMemoryEventBus);
+        let scheduler: SharedScheduler = Arc::new(Scheduler::new(
+            hands.clone(),
+            hand_log.clone(),
+            tables.clone(),
+            bus.clone(),
+            Arc::new(StackedDeck::new(&["As", "Kd", "Ah", "Kc"]).unwrap()),
+            std::time::Duration::from_secs(60),
+        ));
+        let schema = async_graphql::Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+            .data(hands.clone())
+            .data(hand_log)
+            .data(tables.clone())
+            .data(bus)
+            .data(scheduler)
+            .finish();
+
+        let data = execute(
+            &schema,
+            "owner",
+            r#"mutation { createTable(name: "Main", maxSeats: 6) { id } }"#.to_string(),
+        )
+        .await;
+        let table_id = data.into_json().unwrap()["createTable"]["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        for (player, seat) in [("ann", 4), ("bob", 1), ("cat", 2)] {
+            execute(
+                &schema,
+                player,
+                format!(
+                    r#"mutation {{ joinTable(tableId: "{}", seat: {}, buyIn: 1000) {{ id }} }}"#,
+                    table_id, seat
+                ),
+            )
+            .await;
+        }
+        execute(
+            &schema,
+            "cat",
+            format!(r#"mutation {{ leaveTable(tableId: "{}") {{ seat }} }}"#, table_id),
+        )
+        .await;
 
-fn sort_active_players(players: Vec<PlayerInput>) -> Vec<ActivePlayer> {
-    let mut active_players = build_active_players(players);
-    if active_players.len() != 2 {
-        let slice1: Vec<ActivePlayer> = active_players.split_off(2);
-        let slice2: Vec<ActivePlayer> = active_players.drain(..2).collect();
-        active_players.extend(slice1);
-        active_players.extend(slice2);
+        let data = execute(
+            &schema,
+            "owner",
+            format!(r#"mutation {{ deal(dealInput: {{ tableId: "{}" }}) }}"#, table_id),
+        )
+        .await;
+        let hand_id: ID = data.into_json().unwrap()["deal"].as_str().unwrap().into();
+        let hand = storage::load_hand(&hands, &hand_id).await.unwrap();
+        let seated: Vec<(&str, i32)> = hand
+            .players
+            .iter()
+            .map(|p| (p.id.as_str(), p.seat))
+            .collect();
+        assert_eq!(seated, vec![("bob", 1), ("ann", 4)]);
+        assert_eq!(hand.dealer_seat, 1);
+        // The stacked deck deals each player a card in turn.
+        let cards: Vec<&[String]> = hand.players.iter().map(|p| &p.cards[..]).collect();
+        assert_eq!(cards, vec![["As", "Ah"], ["Kd", "Kc"]]);
+        assert_eq!(hand.seed, None);
+
+        let table = storage::load_table(&tables, &table_id.into()).await.unwrap();
+        assert_eq!(table.current_hand_id, Some(hand_id));
+        assert_eq!(table.dealer_seat, Some(1));
+
+        // Only the owner may deal, and the table won't deal again until the
+        // hand is over.
+        let deal = format!(
+            r#"mutation {{ deal(dealInput: {{ tableId: "{}" }}) }}"#,
+            table.id.as_str()
+        );
+        for (user, expected) in [
+            (None, "UNAUTHENTICATED"),
+            (Some("ann"), "NOT_TABLE_OWNER"),
+            (Some("owner"), "HAND_IN_PROGRESS"),
+        ] {
+            let mut request = async_graphql::Request::new(deal.clone());
+            if let Some(user) = user {
+                request = request.data(UserToken(user.to_string()));
+            }
+            let response = schema.execute(request).await;
+            assert_eq!(
+                response.errors[0]
+                    .extensions
+                    .as_ref()
+                    .and_then(|e| e.get("code").cloned()),
+                Some(Value::from(expected))
+            );
+        }
     }
-    active_players
-}
 
-#[cfg(test)]
-use mockall::{automock, mock, predicate::*};
+    #[tokio::test]
+    async fn resubscribing_sends_a_snapshot_then_what_was_missed() {
+        let hands: SharedHandRepository = Arc::new(InMemoryHandRepository::default());
+        let hand_log: SharedHandLogRepository = Arc::new(InMemoryHandLogRepository::default());
+        let tables: SharedTableRepository = Arc::new(InMemoryTableRepository::default());
+        let bus: SharedEventBus = Arc::new(InMemoryEventBus);
+        let id = seed_hand(&hands, &hand_log).await;
+        for _ in 0..3 {
+            let command = next_command(&hands, &id).await.unwrap();
+            play_command(&hands, &hand_log, &id, command, 0).await.unwrap();
+        }
+        let entries = hand_log.find(&id).await.unwrap();
+        let last = entries.last().unwrap().sequence;
+        let schema = async_graphql::Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+            .data(hands.clone())
+            .data(hand_log)
+            .data(tables)
+            .data(bus.clone())
+            .finish();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use async_graphql::{Context, Data, Enum, Object, Result, Schema, Subscription, ID};
-    use mockall::automock;
-    use mockall::mock;
-    use mockall::predicate::*;
+        let request = async_graphql::Request::new(
+            "subscription { handEvent(sinceSequence: 3) { mutationType sequence \
+             snapshot { version toAct } logEntry { sequence kind } } }",
+        )
+        .data(HandToken(id.to_string()))
+        .data(UserToken("sb".to_string()));
+        let mut stream = schema.execute_stream(request);
+        let mut received = vec![];
+        for _ in 0..entries.len() - 2 {
+            let response = stream.next().await.unwrap();
+            assert!(response.errors.is_empty(), "{:?}", response.errors);
+            received.push(response.data.into_json().unwrap()["handEvent"].clone());
+        }
+
+        let hand = storage::load_hand(&hands, &id).await.unwrap();
+        assert_eq!(received[0]["mutationType"], "CREATED");
+        assert_eq!(received[0]["sequence"], last);
+        assert_eq!(received[0]["snapshot"]["version"], 3);
+        assert_eq!(received[0]["snapshot"]["toAct"], hand.to_act.unwrap().as_str());
+        let replayed: Vec<i64> = received[1..]
+            .iter()
+            .map(|event| event["logEntry"]["sequence"].as_i64().unwrap())
+            .collect();
+        assert_eq!(replayed, (4..=last).collect::<Vec<_>>());
+
+        // Live events the snapshot already covers are skipped.
+        let mut next = entries.last().unwrap().clone();
+        bus.publish(
+            Topic::Hand(id.to_string()),
+            BusEvent::Hand(Box::new(HandEventPayload::of_log_entry(next.clone()))),
+        )
+        .await
+        .unwrap();
+        next.sequence += 1;
+        bus.publish(
+            Topic::Hand(id.to_string()),
+            BusEvent::Hand(Box::new(HandEventPayload::of_log_entry(next))),
+        )
+        .await
+        .unwrap();
+        let response = stream.next().await.unwrap();
+        let event = &response.data.into_json().unwrap()["handEvent"];
+        assert_eq!(event["sequence"], last + 1);
+
+        // A filter applies to the snapshot and the replayed entries too.
+        for (mutation_type, first) in [("UPDATED", 4), ("CREATED", last)] {
+            let request = async_graphql::Request::new(format!(
+                "subscription {{ handEvent(mutationType: {}, sinceSequence: 3) \
+                 {{ mutationType sequence }} }}",
+                mutation_type
+            ))
+            .data(HandToken(id.to_string()))
+            .data(UserToken("sb".to_string()));
+            let mut stream = schema.execute_stream(request);
+            let response = stream.next().await.unwrap();
+            let event = &response.data.into_json().unwrap()["handEvent"];
+            assert_eq!(event["mutationType"], mutation_type);
+            assert_eq!(event["sequence"], first);
+        }
+    }
 }
@@ -0,0 +1,594 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use async_graphql::{Result, ID};
+use chrono::Utc;
+use tokio::time::Duration;
+
+use super::engine::table::{self, TableError};
+use super::engine::{GameEvent, GameState, PlayerCommand};
+use super::event_bus::{BusEvent, SharedEventBus};
+use super::model::{DealInput, Hand, Table};
+use super::mutation::{deal_hand, play_command, PlayedCommand};
+use super::shuffler::SharedShuffler;
+use super::simple_broker::Topic;
+use super::storage::{self, SharedHandLogRepository, SharedHandRepository, SharedTableRepository};
+use super::{HandEventPayload, MutationType, TableEvent, TableEventKind};
+
+/// Keeps each table's hands moving: deals the next hand once its countdown
+/// runs out, and acts for players whose clock runs out.
+///
+/// A table has at most one countdown running. It ticks once a `tick`,
+/// publishing the seconds left to the table's subscribers, and pauses as
+/// soon as fewer than two seated players are ready.
+///
+/// When a player's turn comes they have the table's action timeout and then
+/// their time bank to act, after which they check if they can or fold.
+pub struct Scheduler {
+    hands: SharedHandRepository,
+    hand_log: SharedHandLogRepository,
+    tables: SharedTableRepository,
+    bus: SharedEventBus,
+    shuffler: SharedShuffler,
+    tick: Duration,
+    counting: Mutex<HashSet<ID>>,
+}
+
+pub type SharedScheduler = Arc<Scheduler>;
+
+impl Scheduler {
+    pub fn new(
+        hands: SharedHandRepository,
+        hand_log: SharedHandLogRepository,
+        tables: SharedTableRepository,
+        bus: SharedEventBus,
+        shuffler: SharedShuffler,
+        tick: Duration,
+    ) -> Self {
+        Scheduler {
+            hands,
+            hand_log,
+            tables,
+            bus,
+            shuffler,
+            tick,
+            counting: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Starts counting down to the table's next hand, unless a countdown is
+    /// already running. Does nothing while the table can't deal.
+    pub fn schedule(self: &Arc<Self>, table_id: ID) {
+        if !self.counting.lock().unwrap().insert(table_id.clone()) {
+            return;
+        }
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = scheduler.count_down(&table_id).await {
+                println!("Error dealing table {}: {:?}", table_id.as_str(), e);
+            }
+            scheduler.counting.lock().unwrap().remove(&table_id);
+        });
+    }
+
+    /// A server seed for a new table's first hand.
+    pub fn draw_seed(&self) -> Option<String> {
+        self.shuffler.draw_seed()
+    }
+
+    /// Deals the table's next hand and starts the first player's clock.
+    pub async fn deal(self: &Arc<Self>, deal_input: DealInput) -> Result<ID> {
+        self.reconcile(&deal_input.table_id).await?;
+        let id = deal_hand(
+            &self.hands,
+            &self.hand_log,
+            &self.tables,
+            &self.bus,
+            &self.shuffler,
+            deal_input,
+        )
+        .await?;
+        // The hand has been dealt, so not starting its clock isn't an error.
+        let dealt = async {
+            let hand = storage::load_hand(&self.hands, &id).await?;
+            let table = storage::load_table(&self.tables, &hand.table_id).await?;
+            Result::<_>::Ok((hand, table))
+        };
+        match dealt.await {
+            Ok((hand, table)) => self.start_clock(&hand, &table),
+            Err(e) => println!("Error starting the clock of hand {}: {:?}", id.as_str(), e),
+        }
+        Ok(id)
+    }
+
+    /// Applies a player's command, or the one their clock ran out on, and
+    /// tells the hand's subscribers. The next player's clock is started, or
+    /// once the hand is over the table is settled and counts down to the
+    /// next one.
+    pub async fn play(
+        self: &Arc<Self>,
+        id: &ID,
+        command: PlayerCommand,
+        timed_out: bool,
+    ) -> Result<()> {
+        let player_id = command.player_id.clone();
+        let now = Utc::now().timestamp_millis();
+        let PlayedCommand {
+            state,
+            events,
+            overtime,
+            sequence,
+        } = play_command(&self.hands, &self.hand_log, id, command, now).await?;
+        let hand = state.hand();
+
+        let mut payload = HandEventPayload {
+            mutation_type: MutationType::Updated,
+            hand_id: id.clone(),
+            sequence,
+            snapshot: None,
+            log_entry: None,
+            street_event: None,
+            player_event: None,
+            cards: None,
+            pot_awards: vec![],
+            to_act: hand.to_act.clone(),
+            action_deadline: hand.action_deadline,
+            time_bank: None,
+        };
+        let mut game_over = false;
+        for event in events {
+            match event {
+                GameEvent::PlayerActed(player_event) => payload.player_event = Some(player_event),
+                GameEvent::StreetUpdated(street_event) => {
+                    // A new street comes with the board dealt so far.
+                    let street_type = payload.player_event.as_ref().map(|e| e.street_type);
+                    if street_type != Some(street_event.street_type) {
+                        payload.cards = Some(hand.cards.clone());
+                    }
+                    payload.street_event = Some(street_event);
+                }
+                GameEvent::HandCompleted(result) => {
+                    // Including whatever was run out for a showdown.
+                    payload.cards = Some(hand.cards.clone());
+                    payload.pot_awards = result.pot_awards;
+                    game_over = true;
+                }
+            }
+        }
+
+        let updated = storage::update_table(&self.tables, &hand.table_id, |table| {
+            table::record_action(table, &player_id, overtime, timed_out);
+            if game_over {
+                table::settle(table, hand);
+            }
+            Ok(())
+        })
+        .await;
+        if let (Ok(table), Some(to_act)) = (&updated, &hand.to_act) {
+            payload.time_bank = Some(table::time_bank(table, to_act));
+        }
+
+        if let Err(e) = &updated {
+            println!("Error updating table {}: {:?}", hand.table_id.as_str(), e);
+        }
+
+        // The hand has been saved, so the action stands whatever happens
+        // next, and its subscribers hear about it even if the table
+        // couldn't be updated.
+        if let Err(e) = self
+            .bus
+            .publish(
+                Topic::Hand(id.to_string()),
+                BusEvent::Hand(Box::new(payload)),
+            )
+            .await
+        {
+            println!("Error publishing hand {}: {:?}", id.as_str(), e);
+        }
+
+        // A hand that couldn't be settled is settled by the countdown to the
+        // next one instead.
+        match &updated {
+            _ if game_over => self.schedule(hand.table_id.clone()),
+            Ok(table) => self.start_clock(hand, table),
+            Err(_) => {
+                if let Ok(table) = storage::load_table(&self.tables, &hand.table_id).await {
+                    self.start_clock(hand, &table);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Settles the table from its last hand, if that hand finished without
+    /// the table being updated, so the next hand isn't dealt from stale
+    /// stacks.
+    async fn reconcile(&self, table_id: &ID) -> Result<()> {
+        let table = storage::load_table(&self.tables, table_id).await?;
+        let Some(hand_id) = &table.current_hand_id else {
+            return Ok(());
+        };
+        // A deal that failed to store its hand leaves nothing to settle.
+        let Some(hand) = self.hands.find(hand_id).await? else {
+            return Ok(());
+        };
+        if table::needs_settling(&table, &hand) {
+            storage::update_table(&self.tables, table_id, |table| {
+                if table::needs_settling(table, &hand) {
+                    table::settle(table, &hand);
+                }
+                Ok(())
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Acts for the player to act once their deadline and time bank have
+    /// passed, unless the hand has moved on by then.
+    fn start_clock(self: &Arc<Self>, hand: &Hand, table: &Table) {
+        let (Some(player_id), Some(deadline)) = (&hand.to_act, hand.action_deadline) else {
+            return;
+        };
+        let expires = deadline + table::time_bank(table, player_id) as i64 * 1000;
+        let scheduler = self.clone();
+        let id = hand.id.clone();
+        let version = hand.version;
+        tokio::spawn(async move {
+            let wait = (expires - Utc::now().timestamp_millis()).max(0) as u64;
+            tokio::time::sleep(Duration::from_millis(wait)).await;
+            if let Err(e) = scheduler.time_out(&id, version).await {
+                println!("Error timing out hand {}: {:?}", id.as_str(), e);
+            }
+        });
+    }
+
+    async fn time_out(self: &Arc<Self>, id: &ID, version: i64) -> Result<()> {
+        let hand = storage::load_hand(&self.hands, id).await?;
+        if hand.version != version {
+            return Ok(());
+        }
+        match GameState::new(hand).timeout_command() {
+            Some(command) => {
+                println!(
+                    "{} timed out: {:?}",
+                    command.player_id.as_str(),
+                    command.action
+                );
+                self.play(id, command, true).await
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn count_down(self: &Arc<Self>, table_id: &ID) -> Result<()> {
+        let mut remaining = None;
+        loop {
+            self.reconcile(table_id).await?;
+            let table = storage::load_table(&self.tables, table_id).await?;
+            let current = storage::current_hand(&self.hands, &table).await?;
+            match table::deal_in(&table, current.as_ref()) {
+                Ok(_) => {}
+                Err(TableError::NotEnoughPlayers) => {
+                    if remaining.is_some() {
+                        self.publish(table_id, TableEventKind::Paused, None).await?;
+                    }
+                    return Ok(());
+                }
+                // Closed, or someone dealt the hand already.
+                Err(_) => return Ok(()),
+            }
+
+            let seconds = *remaining.get_or_insert(table.config.next_hand_delay);
+            if seconds <= 0 {
+                self.deal(DealInput {
+                    table_id: table_id.clone(),
+                    straddle: None,
+                })
+                .await?;
+                return Ok(());
+            }
+            self.publish(table_id, TableEventKind::Countdown, Some(seconds))
+                .await?;
+            tokio::time::sleep(self.tick).await;
+            remaining = Some(seconds - 1);
+        }
+    }
+
+    async fn publish(
+        &self,
+        table_id: &ID,
+        kind: TableEventKind,
+        seconds_remaining: Option<i32>,
+    ) -> Result<()> {
+        let event = TableEvent {
+            kind,
+            table_id: table_id.clone(),
+            seconds_remaining,
+        };
+        self.bus
+            .publish(Topic::Table(table_id.to_string()), BusEvent::Table(event))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use async_trait::async_trait;
+    use futures_util::StreamExt;
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::bootstrap::schema::event_bus::{EventBus, InMemoryEventBus};
+    use crate::bootstrap::schema::model::{
+        fixtures, HandStatus, PlayerAction, StreetType, TableConfig, TableStatus,
+    };
+    use crate::bootstrap::schema::shuffler::{self, SeededShuffler};
+    use crate::bootstrap::schema::storage::memory::{
+        InMemoryHandLogRepository, InMemoryHandRepository, InMemoryTableRepository,
+    };
+    use crate::bootstrap::schema::storage::{StorageError, TableRepository};
+
+    async fn scheduler(table_id: &str, players: &[&str]) -> SharedScheduler {
+        scheduler_with(
+            table_id,
+            players,
+            TableConfig {
+                next_hand_delay: 2,
+                ..TableConfig::default()
+            },
+        )
+        .await
+    }
+
+    async fn scheduler_with(
+        table_id: &str,
+        players: &[&str],
+        config: TableConfig,
+    ) -> SharedScheduler {
+        let tables = Arc::new(InMemoryTableRepository::default());
+        scheduler_on(tables, table_id, players, config).await
+    }
+
+    async fn scheduler_on(
+        tables: SharedTableRepository,
+        table_id: &str,
+        players: &[&str],
+        config: TableConfig,
+    ) -> SharedScheduler {
+        let mut table = Table {
+            config,
+            ..fixtures::table(table_id)
+        };
+        for (seat, id) in players.iter().enumerate() {
+            table::join(
+                &mut table,
+                &(*id).into(),
+                seat as i32,
+                Decimal::new(1000, 0),
+            )
+            .unwrap();
+        }
+        tables.insert(&table).await.unwrap();
+        Arc::new(Scheduler::new(
+            Arc::new(InMemoryHandRepository::default()),
+            Arc::new(InMemoryHandLogRepository::default()),
+            tables,
+            Arc::new(InMemoryEventBus),
+            Arc::new(SeededShuffler::new(None)),
+            Duration::from_millis(5),
+        ))
+    }
+
+    /// Tables whose saves fail while `failing` is set.
+    #[derive(Default)]
+    struct FlakyTables {
+        tables: InMemoryTableRepository,
+        failing: AtomicBool,
+    }
+
+    #[async_trait]
+    impl TableRepository for FlakyTables {
+        async fn insert(&self, table: &Table) -> Result<()> {
+            self.tables.insert(table).await
+        }
+
+        async fn find(&self, id: &ID) -> Result<Option<Table>> {
+            self.tables.find(id).await
+        }
+
+        async fn list(&self, status: TableStatus) -> Result<Vec<Table>> {
+            self.tables.list(status).await
+        }
+
+        async fn save(&self, table: &Table, expected_version: i64) -> Result<(), StorageError> {
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(StorageError::Backend("unavailable".to_string()));
+            }
+            self.tables.save(table, expected_version).await
+        }
+    }
+
+    fn describe(event: BusEvent) -> String {
+        match event {
+            BusEvent::Table(event) => format!("{:?} {:?}", event.kind, event.seconds_remaining),
+            BusEvent::Deal(_) => "Deal".to_string(),
+            BusEvent::Hand(_) => "Hand".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_down_then_deals() {
+        let scheduler = scheduler("countdown-table", &["ann", "bob"]).await;
+        let mut events = InMemoryEventBus.subscribe(Topic::Table("countdown-table".into()));
+        scheduler.schedule("countdown-table".into());
+        // A second request while counting down is ignored.
+        scheduler.schedule("countdown-table".into());
+
+        let mut seen = Vec::new();
+        for _ in 0..3 {
+            seen.push(describe(events.next().await.unwrap()));
+        }
+        assert_eq!(seen, vec!["Countdown Some(2)", "Countdown Some(1)", "Deal"]);
+        let table = storage::load_table(&scheduler.tables, &"countdown-table".into())
+            .await
+            .unwrap();
+        let hand = storage::load_hand(&scheduler.hands, &table.current_hand_id.unwrap())
+            .await
+            .unwrap();
+        // The seed kept with the hand shuffles the same deck again.
+        let deck = shuffler::replay(hand.seed.as_deref().unwrap(), &hand.client_seed).unwrap();
+        assert_eq!(hand.players[0].cards, vec![deck[0], deck[2]]);
+    }
+
+    #[tokio::test]
+    async fn pauses_when_a_player_leaves() {
+        let scheduler = scheduler("paused-table", &["ann", "bob"]).await;
+        let mut events = InMemoryEventBus.subscribe(Topic::Table("paused-table".into()));
+        scheduler.schedule("paused-table".into());
+        assert_eq!(describe(events.next().await.unwrap()), "Countdown Some(2)");
+
+        storage::update_table(&scheduler.tables, &"paused-table".into(), |table| {
+            table::leave(table, &"bob".into(), None).map(|_| ())
+        })
+        .await
+        .unwrap();
+        let mut next = describe(events.next().await.unwrap());
+        while next.starts_with("Countdown") {
+            next = describe(events.next().await.unwrap());
+        }
+        assert_eq!(next, "Paused None");
+
+        // Nothing is scheduled for a table that can't deal.
+        scheduler.schedule("paused-table".into());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(scheduler.counting.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn players_who_run_out_of_time_fold() {
+        let config = TableConfig {
+            action_timeout: 1,
+            time_bank: 0,
+            ..TableConfig::default()
+        };
+        let scheduler = scheduler_with("clock-table", &["ann", "bob"], config).await;
+        let id = scheduler
+            .deal(DealInput {
+                table_id: "clock-table".into(),
+                straddle: None,
+            })
+            .await
+            .unwrap();
+        let mut events = InMemoryEventBus.subscribe(Topic::Hand(id.to_string()));
+        let hand = storage::load_hand(&scheduler.hands, &id).await.unwrap();
+        assert_eq!(hand.to_act, Some(ID::from("ann")));
+        assert_eq!(hand.action_deadline, Some(hand.created_at + 1000));
+
+        // The button faces the big blind, so can't check.
+        match events.next().await.unwrap() {
+            BusEvent::Hand(payload) => {
+                let event = payload.player_event.unwrap();
+                assert_eq!(event.player_id, "ann");
+                assert_eq!(event.action, PlayerAction::Fold);
+                assert_eq!(payload.to_act, None);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        let table = storage::load_table(&scheduler.tables, &"clock-table".into())
+            .await
+            .unwrap();
+        assert_eq!(table.seats[0].timeouts, 1);
+        assert_eq!(table.seats[1].timeouts, 0);
+    }
+
+    #[tokio::test]
+    async fn each_street_is_published_with_its_cards() {
+        let scheduler = scheduler("board-table", &["ann", "bob"]).await;
+        let id = scheduler
+            .deal(DealInput {
+                table_id: "board-table".into(),
+                straddle: None,
+            })
+            .await
+            .unwrap();
+        let mut events = InMemoryEventBus.subscribe(Topic::Hand(id.to_string()));
+        let hand = storage::load_hand(&scheduler.hands, &id).await.unwrap();
+        assert!(hand.cards.flop.is_empty());
+        let deck = shuffler::replay(hand.seed.as_deref().unwrap(), &hand.client_seed).unwrap();
+
+        for (action, amount) in [(PlayerAction::Call, 10), (PlayerAction::Check, 0)] {
+            let hand = storage::load_hand(&scheduler.hands, &id).await.unwrap();
+            let command = PlayerCommand {
+                player_id: hand.to_act.unwrap(),
+                action,
+                amount: Decimal::new(amount, 0),
+            };
+            scheduler.play(&id, command, false).await.unwrap();
+        }
+
+        let mut cards = Vec::new();
+        for _ in 0..2 {
+            match events.next().await.unwrap() {
+                BusEvent::Hand(payload) => cards.push(payload.cards),
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+        assert_eq!(cards[0], None);
+        let board = cards[1].clone().unwrap();
+        // The card after the hole cards is burned before the flop.
+        assert_eq!(board.flop, deck[5..8]);
+        assert_eq!(board.turn, "");
+
+        let hand = storage::load_hand(&scheduler.hands, &id).await.unwrap();
+        assert_eq!(hand.street_events.last().unwrap().street_type, StreetType::Flop);
+        assert_eq!(hand.deck, deck[8..]);
+    }
+
+    #[tokio::test]
+    async fn hands_the_table_missed_are_settled_before_the_next_deal() {
+        let tables = Arc::new(FlakyTables::default());
+        let config = TableConfig {
+            next_hand_delay: 1,
+            ..TableConfig::default()
+        };
+        let scheduler = scheduler_on(tables.clone(), "flaky-table", &["ann", "bob"], config).await;
+        let id = scheduler
+            .deal(DealInput {
+                table_id: "flaky-table".into(),
+                straddle: None,
+            })
+            .await
+            .unwrap();
+        let hand = storage::load_hand(&scheduler.hands, &id).await.unwrap();
+        let mut events = InMemoryEventBus.subscribe(Topic::Table("flaky-table".into()));
+
+        tables.failing.store(true, Ordering::SeqCst);
+        let command = PlayerCommand {
+            player_id: hand.to_act.clone().unwrap(),
+            action: PlayerAction::Fold,
+            amount: Decimal::ZERO,
+        };
+        // The fold stands even though the table couldn't be updated.
+        scheduler.play(&id, command, false).await.unwrap();
+        let hand = storage::load_hand(&scheduler.hands, &id).await.unwrap();
+        assert_eq!(hand.status, HandStatus::Complete);
+        tables.failing.store(false, Ordering::SeqCst);
+
+        let mut next = describe(events.next().await.unwrap());
+        while next.starts_with("Countdown") {
+            next = describe(events.next().await.unwrap());
+        }
+        assert_eq!(next, "Deal");
+        let table = storage::load_table(&scheduler.tables, &"flaky-table".into())
+            .await
+            .unwrap();
+        assert_eq!(table.settled_hand_id, Some(id));
+        let stacks: Vec<Decimal> = table.seats.iter().map(|s| s.stack).collect();
+        let settled: Vec<Decimal> = hand.players.iter().map(|p| p.stack).collect();
+        assert_eq!(stacks, settled);
+        assert_ne!(stacks, vec![Decimal::new(1000, 0); 2]);
+    }
+}
@@ -0,0 +1,374 @@
+use std::sync::{Arc, Mutex};
+
+use deuces_rs::builder::CardShuffler;
+use hmac::{Hmac, Mac};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+
+use super::model::{Hand, HandStatus, HandVerification};
+
+/// Every card, in the order a stacked deck deals the ones it wasn't given.
+pub const DECK: [&str; 52] = [
+    "Ac", "Ad", "Ah", "As", "2c", "2d", "2h", "2s", "3c", "3d", "3h", "3s", "4c", "4d", "4h", "4s",
+    "5c", "5d", "5h", "5s", "6c", "6d", "6h", "6s", "7c", "7d", "7h", "7s", "8c", "8d", "8h", "8s",
+    "9c", "9d", "9h", "9s", "Tc", "Td", "Th", "Ts", "Jc", "Jd", "Jh", "Js", "Qc", "Qd", "Qh", "Qs",
+    "Kc", "Kd", "Kh", "Ks",
+];
+
+/// A deck ready to deal from, with the server seed that shuffles it the
+/// same way again if there is one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deck {
+    pub cards: Vec<&'static str>,
+    /// Hex encoded.
+    pub seed: Option<String>,
+}
+
+impl Deck {
+    /// The hash of the seed, published before any card is seen so that the
+    /// seed revealed after the hand can be checked against it.
+    pub fn commitment(&self) -> Option<String> {
+        self.seed.as_deref().and_then(|seed| commitment(seed).ok())
+    }
+}
+
+impl CardShuffler for Deck {
+    fn shuffle(&self) -> Vec<&'static str> {
+        self.cards.clone()
+    }
+}
+
+/// Decides the order of the cards for each hand dealt. Shufflers that use
+/// a seed mix in the players' `client_seed`, so the server can't pick the
+/// deck on its own.
+pub trait DeckShuffler: Send + Sync {
+    /// A server seed for a table's next hand, hex encoded, or `None` if the
+    /// shuffler doesn't use one. It is drawn, and its hash published, before
+    /// the client seeds it is mixed with are known.
+    fn draw_seed(&self) -> Option<String>;
+
+    /// Shuffles a hand's deck from the seed drawn for it.
+    fn shuffle(&self, seed: Option<&str>, client_seed: &str) -> Result<Deck, String>;
+}
+
+pub type SharedShuffler = Arc<dyn DeckShuffler>;
+
+/// The deck a server seed and client seed shuffle to: a Fisher-Yates
+/// shuffle driven by ChaCha20, keyed with HMAC-SHA256 of the client seed
+/// under the server seed.
+pub fn shuffle_with(seed: [u8; 32], client_seed: &str) -> Vec<&'static str> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&seed).expect("HMAC takes keys of any size");
+    mac.update(client_seed.as_bytes());
+    let mut rng = ChaCha20Rng::from_seed(mac.finalize().into_bytes().into());
+    let mut cards = DECK.to_vec();
+    for i in (1..cards.len()).rev() {
+        cards.swap(i, rng.gen_range(0..=i));
+    }
+    cards
+}
+
+/// The deck a hand's recorded seeds shuffle to, to reproduce it.
+pub fn replay(seed: &str, client_seed: &str) -> Result<Vec<&'static str>, String> {
+    Ok(shuffle_with(decode(seed)?, client_seed))
+}
+
+/// The SHA-256 hash of a hex encoded server seed, hex encoded.
+pub fn commitment(seed: &str) -> Result<String, String> {
+    Ok(hex::encode(Sha256::digest(decode(seed)?)))
+}
+
+fn decode(seed: &str) -> Result<[u8; 32], String> {
+    hex::decode(seed)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| "Seeds are 32 bytes of hex".to_string())
+}
+
+/// Re-shuffles a finished hand's deck from its revealed seed and checks it
+/// against the commitment and the cards that were dealt.
+pub fn verify(hand: &Hand) -> Result<HandVerification, String> {
+    if hand.status != HandStatus::Complete {
+        return Err(format!(
+            "Hand {} is still being played, its seed is revealed when it ends",
+            hand.id.as_str()
+        ));
+    }
+    let (Some(seed), Some(seed_hash)) = (&hand.seed, &hand.seed_hash) else {
+        return Err(format!(
+            "Hand {} was not dealt from a seed",
+            hand.id.as_str()
+        ));
+    };
+    let deck = replay(seed, &hand.client_seed)?;
+    Ok(HandVerification {
+        hand_id: hand.id.clone(),
+        seed: seed.clone(),
+        seed_hash: seed_hash.clone(),
+        client_seed: hand.client_seed.clone(),
+        commitment_matches: commitment(seed)? == *seed_hash,
+        cards_match: dealt_from(&deck, hand),
+        deck: deck.iter().map(|card| card.to_string()).collect(),
+    })
+}
+
+/// Whether the hand was dealt from the top of `deck`: a card to each player
+/// in turn, a second round, then as much of the flop, turn and river as the
+/// hand got to, with a card burned before each.
+fn dealt_from(deck: &[&str], hand: &Hand) -> bool {
+    let players = hand.players.len();
+    let flop = players * 2;
+    let board = hand
+        .cards
+        .flop
+        .iter()
+        .chain([&hand.cards.turn, &hand.cards.river])
+        .filter(|card| !card.is_empty());
+    [1, 2, 3, 5, 7]
+        .iter()
+        .zip(board)
+        .all(|(i, card)| deck.get(flop + i).is_some_and(|dealt| dealt == card))
+        && hand
+            .players
+            .iter()
+            .enumerate()
+            .all(|(i, player)| player.cards == [deck[i], deck[i + players]])
+}
+
+/// Shuffles each hand with a fresh seed, which is kept with the hand.
+///
+/// The seeds are drawn from the OS unless a session seed is given, in which
+/// case every run deals the same hands in the same order.
+pub struct SeededShuffler {
+    seeds: Mutex<ChaCha20Rng>,
+}
+
+impl SeededShuffler {
+    pub fn new(session_seed: Option<u64>) -> Self {
+        let seeds = match session_seed {
+            Some(seed) => ChaCha20Rng::seed_from_u64(seed),
+            None => ChaCha20Rng::from_entropy(),
+        };
+        SeededShuffler {
+            seeds: Mutex::new(seeds),
+        }
+    }
+}
+
+impl DeckShuffler for SeededShuffler {
+    fn draw_seed(&self) -> Option<String> {
+        let seed: [u8; 32] = self.seeds.lock().unwrap().gen();
+        Some(hex::encode(seed))
+    }
+
+    fn shuffle(&self, seed: Option<&str>, client_seed: &str) -> Result<Deck, String> {
+        let seed = seed.ok_or_else(|| "No seed was drawn for the hand".to_string())?;
+        Ok(Deck {
+            cards: replay(seed, client_seed)?,
+            seed: Some(seed.to_string()),
+        })
+    }
+}
+
+/// Deals the same scripted cards every hand, followed by the rest of the
+/// deck in order. For tests and rigged demos only.
+pub struct StackedDeck {
+    cards: Vec<&'static str>,
+}
+
+impl StackedDeck {
+    /// `cards` are dealt first: each player's first hole card, then each
+    /// player's second, then the flop, turn and river, each after a burn card.
+    pub fn new(cards: &[&str]) -> Result<Self, String> {
+        let mut stacked: Vec<&'static str> = Vec::new();
+        for card in cards {
+            let card = DECK
+                .iter()
+                .find(|c| *c == card)
+                .ok_or_else(|| format!("{} is not a card", card))?;
+            if stacked.contains(card) {
+                return Err(format!("{} is stacked twice", card));
+            }
+            stacked.push(card);
+        }
+        stacked.extend(DECK.iter().filter(|c| !cards.contains(c)));
+        Ok(StackedDeck { cards: stacked })
+    }
+}
+
+impl DeckShuffler for StackedDeck {
+    fn draw_seed(&self) -> Option<String> {
+        None
+    }
+
+    fn shuffle(&self, _seed: Option<&str>, _client_seed: &str) -> Result<Deck, String> {
+        Ok(Deck {
+            cards: self.cards.clone(),
+            seed: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deuces_rs::builder::Dealer;
+    use deuces_rs::GameDealer;
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::bootstrap::schema::engine::start_hand;
+    use crate::bootstrap::schema::model::{Cards, Player, TableConfig};
+
+    /// A finished hand dealt to `players` from a seeded deck.
+    fn dealt(players: usize) -> Hand {
+        let shuffler = SeededShuffler::new(None);
+        let deck = shuffler
+            .shuffle(shuffler.draw_seed().as_deref(), "ann,bob")
+            .unwrap();
+        let seed = deck.seed.clone();
+        let seed_hash = deck.commitment();
+        let board: Vec<String> = deck.cards[players * 2..]
+            .iter()
+            .map(|card| card.to_string())
+            .collect();
+        let deal = GameDealer::new(deck).deal(players);
+        let players = deal
+            .hands
+            .iter()
+            .enumerate()
+            .map(|(i, dealt)| Player {
+                id: i.to_string().into(),
+                seat: i as i32,
+                stack: Decimal::new(1000, 0),
+                cards: dealt.cards.clone(),
+                score: dealt.score,
+                description: dealt.description.clone(),
+                best_cards: vec![],
+                shown: false,
+                missed_small_blind: false,
+                missed_big_blind: false,
+            })
+            .collect();
+        let mut hand = start_hand(
+            "hand".into(),
+            "table".into(),
+            players,
+            vec![],
+            TableConfig::default(),
+            0,
+            false,
+        );
+        // As if it ran to showdown, burning a card before each street.
+        hand.cards = Cards {
+            flop: board[1..4].to_vec(),
+            turn: board[5].clone(),
+            river: board[7].clone(),
+        };
+        hand.seed = seed;
+        hand.seed_hash = seed_hash;
+        hand.client_seed = "ann,bob".to_string();
+        hand.status = HandStatus::Complete;
+        hand
+    }
+
+    #[test]
+    fn finished_hands_verify_against_their_commitment() {
+        let hand = dealt(3);
+        let verification = verify(&hand).unwrap();
+        assert!(verification.commitment_matches && verification.cards_match);
+        assert_eq!(verification.deck[0], hand.players[0].cards[0]);
+
+        let mut swapped = hand.clone();
+        swapped.players[0].cards.swap(0, 1);
+        assert!(!verify(&swapped).unwrap().cards_match);
+
+        let mut rigged = hand.clone();
+        std::mem::swap(&mut rigged.cards.turn, &mut rigged.cards.river);
+        assert!(!verify(&rigged).unwrap().cards_match);
+
+        // The flop comes after a burn card, not straight off the top.
+        let mut unburned = hand.clone();
+        unburned.cards.flop = verification.deck[6..9].to_vec();
+        assert!(!verify(&unburned).unwrap().cards_match);
+
+        // A hand that ended before the flop has only hole cards to check.
+        let mut folded = hand.clone();
+        folded.cards = Cards::default();
+        assert!(verify(&folded).unwrap().cards_match);
+
+        let mut other_seed = hand.clone();
+        other_seed.client_seed = "ann".to_string();
+        assert!(!verify(&other_seed).unwrap().cards_match);
+
+        let mut recommitted = hand.clone();
+        recommitted.seed_hash = commitment(&"00".repeat(32)).ok();
+        assert!(!verify(&recommitted).unwrap().commitment_matches);
+
+        let mut playing = hand;
+        playing.status = HandStatus::InProgress;
+        assert!(verify(&playing).is_err());
+    }
+
+    #[test]
+    fn recorded_seeds_reproduce_the_deck() {
+        let shuffler = SeededShuffler::new(None);
+        let seed = shuffler.draw_seed().unwrap();
+        let deck = shuffler.shuffle(Some(&seed), "ann").unwrap();
+        let mut sorted = deck.cards.clone();
+        sorted.sort();
+        sorted.dedup();
+        assert_eq!(sorted.len(), 52);
+        assert_eq!(deck.seed.as_deref(), Some(seed.as_str()));
+        assert_eq!(replay(&seed, "ann"), Ok(deck.cards.clone()));
+        assert_ne!(replay(&seed, "bob"), Ok(deck.cards.clone()));
+        assert_ne!(shuffler.draw_seed(), Some(seed));
+        assert!(replay("abc", "ann").is_err());
+        assert!(shuffler.shuffle(None, "ann").is_err());
+
+        // A session seed deals the same hands every run.
+        let first: Vec<Option<String>> = (0..3)
+            .map(|_| SeededShuffler::new(Some(7)).draw_seed())
+            .collect();
+        assert_eq!(first[0], first[1]);
+        assert_eq!(first[1], first[2]);
+    }
+
+    #[test]
+    fn commitments_hash_the_server_seed() {
+        let seed = "00".repeat(32);
+        assert_eq!(
+            commitment(&seed),
+            Ok("66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925".to_string())
+        );
+        let shuffler = SeededShuffler::new(Some(7));
+        let deck = shuffler
+            .shuffle(shuffler.draw_seed().as_deref(), "")
+            .unwrap();
+        assert_eq!(
+            deck.commitment(),
+            commitment(deck.seed.as_deref().unwrap()).ok()
+        );
+        assert_eq!(
+            StackedDeck::new(&[])
+                .unwrap()
+                .shuffle(None, "")
+                .unwrap()
+                .commitment(),
+            None
+        );
+    }
+
+    #[test]
+    fn stacked_decks_deal_the_scripted_cards_first() {
+        let deck = StackedDeck::new(&["As", "Kd", "Ah", "Kc"])
+            .unwrap()
+            .shuffle(None, "ann")
+            .unwrap();
+        assert_eq!(deck.cards[..5], ["As", "Kd", "Ah", "Kc", "Ac"]);
+        assert_eq!(deck.cards.len(), 52);
+        assert_eq!(deck.seed, None);
+
+        assert!(StackedDeck::new(&["As", "As"]).is_err());
+        assert!(StackedDeck::new(&["1x"]).is_err());
+    }
+}
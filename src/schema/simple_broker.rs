@@ -10,30 +10,56 @@ use std::{
 use futures_channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use futures_util::{Stream, StreamExt};
 use once_cell::sync::Lazy;
+use serde_derive::{Deserialize, Serialize};
 use slab::Slab;
 
+/// What a subscription listens to, so events only reach the table, hand or
+/// user they concern.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize, Serialize)]
+pub enum Topic {
+    Table(String),
+    Hand(String),
+    User(String),
+}
+
+type SubscriberMap = HashMap<(TypeId, Topic), Box<dyn Any + Send>>;
 
-static SUBSCRIBERS: Lazy<Mutex<HashMap<TypeId, Box<dyn Any + Send>>>> = Lazy::new(Default::default);
+static SUBSCRIBERS: Lazy<Mutex<SubscriberMap>> = Lazy::new(Default::default);
 
 struct Senders<T>(Slab<UnboundedSender<T>>);
 
-struct BrokerStream<T: Sync + Send + Clone + 'static>(usize, UnboundedReceiver<T>);
+struct BrokerStream<T: Sync + Send + Clone + 'static>(Topic, usize, UnboundedReceiver<T>);
 
-fn with_senders<T, F, R>(f: F) -> R
+fn with_senders<T, F, R>(topic: &Topic, f: F) -> R
 where
     T: Sync + Send + Clone + 'static,
     F: FnOnce(&mut Senders<T>) -> R,
 {
     let mut map = SUBSCRIBERS.lock().unwrap();
     let senders = map
-        .entry(TypeId::of::<Senders<T>>())
+        .entry((TypeId::of::<Senders<T>>(), topic.clone()))
         .or_insert_with(|| Box::new(Senders::<T>(Default::default())));
     f(senders.downcast_mut::<Senders<T>>().unwrap())
 }
 
 impl<T: Sync + Send + Clone + 'static> Drop for BrokerStream<T> {
     fn drop(&mut self) {
-        with_senders::<T, _, _>(|senders| senders.0.remove(self.0));
+        let mut map = SUBSCRIBERS.lock().unwrap();
+        let key = (TypeId::of::<Senders<T>>(), self.0.clone());
+        let empty = match map
+            .get_mut(&key)
+            .and_then(|senders| senders.downcast_mut::<Senders<T>>())
+        {
+            Some(senders) => {
+                senders.0.remove(self.1);
+                senders.0.is_empty()
+            }
+            None => false,
+        };
+        // The last listener gone, forget the topic.
+        if empty {
+            map.remove(&key);
+        }
     }
 }
 
@@ -41,7 +67,7 @@ impl<T: Sync + Send + Clone + 'static> Stream for BrokerStream<T> {
     type Item = T;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.1.poll_next_unpin(cx)
+        self.2.poll_next_unpin(cx)
     }
 }
 
@@ -49,21 +75,68 @@ impl<T: Sync + Send + Clone + 'static> Stream for BrokerStream<T> {
 pub struct SimpleBroker<T>(PhantomData<T>);
 
 impl<T: Sync + Send + Clone + 'static> SimpleBroker<T> {
-    /// Publish a message that all subscription streams can receive.
-    pub fn publish(msg: T) {
-        with_senders::<T, _, _>(|senders| {
+    /// Publish a message that all subscription streams on the topic can receive.
+    pub fn publish(topic: Topic, msg: T) {
+        let mut map = SUBSCRIBERS.lock().unwrap();
+        if let Some(senders) = map
+            .get_mut(&(TypeId::of::<Senders<T>>(), topic))
+            .and_then(|senders| senders.downcast_mut::<Senders<T>>())
+        {
             for (_, sender) in senders.0.iter_mut() {
                 sender.start_send(msg.clone()).ok();
             }
-        });
+        }
     }
 
-    /// Subscribe to the message of the specified type and returns a `Stream`.
-    pub fn subscribe() -> impl Stream<Item = T> {
-        with_senders::<T, _, _>(|senders| {
+    /// Subscribe to messages of the specified type on a topic and returns a `Stream`.
+    pub fn subscribe(topic: Topic) -> impl Stream<Item = T> {
+        with_senders::<T, _, _>(&topic, |senders| {
             let (tx, rx) = mpsc::unbounded();
             let id = senders.0.insert(tx);
-            BrokerStream(id, rx)
+            BrokerStream(topic.clone(), id, rx)
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_subscribed<T: 'static>(topic: &Topic) -> bool {
+        SUBSCRIBERS
+            .lock()
+            .unwrap()
+            .contains_key(&(TypeId::of::<Senders<T>>(), topic.clone()))
+    }
+
+    #[tokio::test]
+    async fn only_subscribers_of_a_topic_receive_its_messages() {
+        let table = Topic::Table("broker-table-1".into());
+        let other = Topic::Table("broker-table-2".into());
+        let mut stream = SimpleBroker::<u32>::subscribe(table.clone());
+        let mut other_stream = SimpleBroker::<u32>::subscribe(other.clone());
+
+        SimpleBroker::publish(other, 2u32);
+        SimpleBroker::publish(table, 1u32);
+
+        assert_eq!(stream.next().await, Some(1));
+        assert_eq!(other_stream.next().await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn topic_is_removed_when_last_listener_drops() {
+        let hand = Topic::Hand("broker-hand".into());
+        let first = SimpleBroker::<String>::subscribe(hand.clone());
+        let second = SimpleBroker::<String>::subscribe(hand.clone());
+        assert!(is_subscribed::<String>(&hand));
+
+        drop(first);
+        assert!(is_subscribed::<String>(&hand));
+        drop(second);
+        assert!(!is_subscribed::<String>(&hand));
+
+        // Publishing to a topic nobody listens to doesn't bring it back.
+        SimpleBroker::publish(hand.clone(), "hello".to_string());
+        assert!(!is_subscribed::<String>(&hand));
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_graphql::{Result, ID};
+use async_trait::async_trait;
+
+use super::{
+    HandCursor, HandFilter, HandLogRepository, HandRepository, StorageError, TableRepository,
+};
+use crate::bootstrap::schema::model::{Hand, HandLogEntry, Table, TableStatus};
+
+/// Keeps hands in memory, for development and tests.
+#[derive(Default)]
+pub struct InMemoryHandRepository {
+    hands: Mutex<HashMap<ID, Hand>>,
+}
+
+#[async_trait]
+impl HandRepository for InMemoryHandRepository {
+    async fn insert(&self, hand: &Hand) -> Result<()> {
+        let mut hands = self.hands.lock().unwrap();
+        if hands.contains_key(&hand.id) {
+            return Err(format!("Hand {} already exists", hand.id.as_str()).into());
+        }
+        hands.insert(hand.id.clone(), hand.clone());
+        Ok(())
+    }
+
+    async fn find(&self, id: &ID) -> Result<Option<Hand>> {
+        Ok(self.hands.lock().unwrap().get(id).cloned())
+    }
+
+    async fn list(
+        &self,
+        filter: &HandFilter,
+        after: Option<&HandCursor>,
+        limit: i64,
+    ) -> Result<Vec<Hand>> {
+        let hands = self.hands.lock().unwrap();
+        let mut listed: Vec<&Hand> = hands
+            .values()
+            .filter(|hand| filter.matches(hand))
+            .filter(|hand| after.is_none_or(|after| HandCursor::of(hand) < *after))
+            .collect();
+        listed.sort_by_key(|hand| std::cmp::Reverse(HandCursor::of(hand)));
+        Ok(listed
+            .into_iter()
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn save(&self, hand: &Hand, expected_version: i64) -> Result<(), StorageError> {
+        let mut hands = self.hands.lock().unwrap();
+        let stored = hands
+            .get_mut(&hand.id)
+            .ok_or_else(|| StorageError::NotFound(hand.id.clone()))?;
+        if stored.version != expected_version {
+            return Err(StorageError::Conflict {
+                id: hand.id.clone(),
+                version: expected_version,
+            });
+        }
+        *stored = hand.clone();
+        Ok(())
+    }
+}
+
+/// Keeps tables in memory, for development and tests.
+#[derive(Default)]
+pub struct InMemoryTableRepository {
+    tables: Mutex<HashMap<ID, Table>>,
+}
+
+#[async_trait]
+impl TableRepository for InMemoryTableRepository {
+    async fn insert(&self, table: &Table) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if tables.contains_key(&table.id) {
+            return Err(format!("Table {} already exists", table.id.as_str()).into());
+        }
+        tables.insert(table.id.clone(), table.clone());
+        Ok(())
+    }
+
+    async fn find(&self, id: &ID) -> Result<Option<Table>> {
+        Ok(self.tables.lock().unwrap().get(id).cloned())
+    }
+
+    async fn list(&self, status: TableStatus) -> Result<Vec<Table>> {
+        let tables = self.tables.lock().unwrap();
+        let mut listed: Vec<Table> = tables
+            .values()
+            .filter(|table| table.status == status)
+            .cloned()
+            .collect();
+        listed.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        Ok(listed)
+    }
+
+    async fn save(&self, table: &Table, expected_version: i64) -> Result<(), StorageError> {
+        let mut tables = self.tables.lock().unwrap();
+        let stored = tables
+            .get_mut(&table.id)
+            .ok_or_else(|| StorageError::NotFound(table.id.clone()))?;
+        if stored.version != expected_version {
+            return Err(StorageError::Conflict {
+                id: table.id.clone(),
+                version: expected_version,
+            });
+        }
+        *stored = table.clone();
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryHandLogRepository {
+    entries: Mutex<HashMap<ID, Vec<HandLogEntry>>>,
+}
+
+#[async_trait]
+impl HandLogRepository for InMemoryHandLogRepository {
+    async fn find(&self, hand_id: &ID) -> Result<Vec<HandLogEntry>> {
+        Ok(self
+            .entries
+            .lock()
+            .unwrap()
+            .get(hand_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn append(&self, entries: &[HandLogEntry]) -> Result<()> {
+        let mut log = self.entries.lock().unwrap();
+        for entry in entries {
+            // Writers may append out of turn, but never share a sequence.
+            let hand_log = log.entry(entry.hand_id.clone()).or_default();
+            match hand_log.binary_search_by_key(&entry.sequence, |e| e.sequence) {
+                Ok(_) => {
+                    return Err(format!(
+                        "Hand {} already has log entry {}",
+                        entry.hand_id.as_str(),
+                        entry.sequence
+                    )
+                    .into())
+                }
+                Err(i) => hand_log.insert(i, entry.clone()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::schema::model::fixtures::{self, hand};
+
+    #[tokio::test]
+    async fn hands_are_saved_and_found() {
+        let hands = InMemoryHandRepository::default();
+        assert_eq!(hands.find(&"h1".into()).await.unwrap(), None);
+
+        hands.insert(&hand("h1")).await.unwrap();
+        assert!(hands.insert(&hand("h1")).await.is_err());
+
+        let mut updated = hand("h1");
+        updated.dealer_seat = 3;
+        updated.version = 1;
+        hands.save(&updated, 0).await.unwrap();
+        assert_eq!(hands.find(&"h1".into()).await.unwrap(), Some(updated));
+
+        assert_eq!(
+            hands.save(&hand("h2"), 0).await,
+            Err(StorageError::NotFound("h2".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn stale_saves_conflict() {
+        let hands = InMemoryHandRepository::default();
+        hands.insert(&hand("h1")).await.unwrap();
+
+        let mut first = hand("h1");
+        first.version = 1;
+        hands.save(&first, 0).await.unwrap();
+
+        let mut second = hand("h1");
+        second.version = 1;
+        second.dealer_seat = 5;
+        let err = hands.save(&second, 0).await.unwrap_err();
+        assert_eq!(err.code(), "CONFLICT");
+        assert_eq!(hands.find(&"h1".into()).await.unwrap(), Some(first));
+    }
+
+    #[tokio::test]
+    async fn hands_are_listed_newest_first_after_the_cursor() {
+        let hands = InMemoryHandRepository::default();
+        for (id, table_id, created_at) in [
+            ("h1", "t1", 100),
+            ("h2", "t2", 200),
+            ("h3", "t1", 300),
+            ("h4", "t1", 300),
+        ] {
+            let mut hand = hand(id);
+            hand.table_id = table_id.into();
+            hand.created_at = created_at;
+            hands.insert(&hand).await.unwrap();
+        }
+        let ids = |listed: Vec<Hand>| -> Vec<String> {
+            listed.into_iter().map(|h| h.id.to_string()).collect()
+        };
+
+        let filter = HandFilter {
+            table_id: Some("t1".into()),
+            ..HandFilter::default()
+        };
+        let page = hands.list(&filter, None, 2).await.unwrap();
+        let cursor = HandCursor::of(page.last().unwrap());
+        assert_eq!(ids(page), vec!["h4", "h3"]);
+        let page = hands.list(&filter, Some(&cursor), 2).await.unwrap();
+        assert_eq!(ids(page), vec!["h1"]);
+
+        let filter = HandFilter {
+            from: Some(200),
+            to: Some(300),
+            ..HandFilter::default()
+        };
+        assert_eq!(ids(hands.list(&filter, None, 10).await.unwrap()), vec!["h2"]);
+    }
+
+    #[tokio::test]
+    async fn tables_are_listed_by_status_and_saved_by_version() {
+        let tables = InMemoryTableRepository::default();
+        for (id, status) in [
+            ("t2", TableStatus::Open),
+            ("t1", TableStatus::Open),
+            ("t3", TableStatus::Closed),
+        ] {
+            let table = Table {
+                status,
+                ..fixtures::table(id)
+            };
+            tables.insert(&table).await.unwrap();
+        }
+        let open: Vec<String> = tables
+            .list(TableStatus::Open)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|t| t.id.to_string())
+            .collect();
+        assert_eq!(open, vec!["t1", "t2"]);
+
+        let mut table = tables.find(&"t1".into()).await.unwrap().unwrap();
+        table.version = 1;
+        tables.save(&table, 0).await.unwrap();
+        assert_eq!(tables.save(&table, 0).await.unwrap_err().code(), "CONFLICT");
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt;
+use std::sync::Arc;
+
+use async_graphql::{ErrorExtensions, Result, ID};
+use async_trait::async_trait;
+use serde_derive::{Deserialize, Serialize};
+
+use super::engine::table::TableError;
+use super::model::{Hand, HandLogEntry, HandStatus, Table, TableStatus};
+
+pub mod memory;
+pub mod mongo;
+
+/// Where hands are kept between actions.
+#[async_trait]
+pub trait HandRepository: Send + Sync {
+    async fn insert(&self, hand: &Hand) -> Result<()>;
+
+    async fn find(&self, id: &ID) -> Result<Option<Hand>>;
+
+    /// Up to `limit` hands matching the filter, newest first, starting after
+    /// the cursor.
+    async fn list(
+        &self,
+        filter: &HandFilter,
+        after: Option<&HandCursor>,
+        limit: i64,
+    ) -> Result<Vec<Hand>>;
+
+    /// Replaces a stored hand with its latest state, as long as the stored
+    /// hand is still at `expected_version`.
+    async fn save(&self, hand: &Hand, expected_version: i64) -> Result<(), StorageError>;
+}
+
+/// Where tables, their seats and their configs are kept.
+#[async_trait]
+pub trait TableRepository: Send + Sync {
+    async fn insert(&self, table: &Table) -> Result<()>;
+
+    async fn find(&self, id: &ID) -> Result<Option<Table>>;
+
+    /// Tables with the status, oldest first.
+    async fn list(&self, status: TableStatus) -> Result<Vec<Table>>;
+
+    /// Replaces a stored table, as long as it is still at `expected_version`.
+    async fn save(&self, table: &Table, expected_version: i64) -> Result<(), StorageError>;
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StorageError {
+    /// Someone else saved the hand or table first; reload it and try again.
+    Conflict { id: ID, version: i64 },
+    NotFound(ID),
+    Backend(String),
+}
+
+impl StorageError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            StorageError::Conflict { .. } => "CONFLICT",
+            StorageError::NotFound(_) => "NOT_FOUND",
+            StorageError::Backend(_) => "STORAGE",
+        }
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Conflict { id, version } => write!(
+                f,
+                "Record {} was changed since version {}, retry the action",
+                id.as_str(),
+                version
+            ),
+            StorageError::NotFound(id) => write!(f, "Record {} not found", id.as_str()),
+            StorageError::Backend(message) => write!(f, "Storage error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl ErrorExtensions for StorageError {
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| {
+            e.set("code", self.code());
+            if let StorageError::Conflict { version, .. } = self {
+                e.set("retryable", true);
+                e.set("version", *version);
+            }
+        })
+    }
+}
+
+impl From<mongodb::error::Error> for StorageError {
+    fn from(e: mongodb::error::Error) -> Self {
+        StorageError::Backend(e.to_string())
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for StorageError {
+    fn from(e: mongodb::bson::ser::Error) -> Self {
+        StorageError::Backend(e.to_string())
+    }
+}
+
+/// Which hands to list. Times are milliseconds since the Unix epoch, `from`
+/// inclusive and `to` exclusive.
+#[derive(Clone, Debug, Default)]
+pub struct HandFilter {
+    pub table_id: Option<ID>,
+    pub player_id: Option<ID>,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub status: Option<HandStatus>,
+}
+
+impl HandFilter {
+    pub fn matches(&self, hand: &Hand) -> bool {
+        self.table_id.as_ref().is_none_or(|id| hand.table_id == *id)
+            && self
+                .player_id
+                .as_ref()
+                .is_none_or(|id| hand.players.iter().any(|p| p.id == *id))
+            && self.from.is_none_or(|from| hand.created_at >= from)
+            && self.to.is_none_or(|to| hand.created_at < to)
+            && self.status.is_none_or(|status| hand.status == status)
+    }
+}
+
+/// Where a hand sits in a listing, ordered newest first.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub struct HandCursor {
+    pub created_at: i64,
+    pub id: ID,
+}
+
+impl HandCursor {
+    pub fn of(hand: &Hand) -> Self {
+        HandCursor {
+            created_at: hand.created_at,
+            id: hand.id.clone(),
+        }
+    }
+}
+
+/// The append-only log of everything that happened in each hand.
+#[async_trait]
+pub trait HandLogRepository: Send + Sync {
+    /// Entries for a hand in sequence order.
+    async fn find(&self, hand_id: &ID) -> Result<Vec<HandLogEntry>>;
+
+    async fn append(&self, entries: &[HandLogEntry]) -> Result<()>;
+}
+
+pub type SharedHandRepository = Arc<dyn HandRepository>;
+
+pub type SharedHandLogRepository = Arc<dyn HandLogRepository>;
+
+pub type SharedTableRepository = Arc<dyn TableRepository>;
+
+/// Loads a hand, failing if there is no hand with the id.
+pub async fn load_hand(hands: &SharedHandRepository, id: &ID) -> Result<Hand> {
+    hands
+        .find(id)
+        .await?
+        .ok_or_else(|| "No document found with the specified id".into())
+}
+
+/// Loads a table, failing if there is no table with the id.
+pub async fn load_table(tables: &SharedTableRepository, id: &ID) -> Result<Table> {
+    tables
+        .find(id)
+        .await?
+        .ok_or_else(|| format!("Table {} not found", id.as_str()).into())
+}
+
+/// The table's current hand, if it is still being played.
+pub async fn current_hand(hands: &SharedHandRepository, table: &Table) -> Result<Option<Hand>> {
+    let hand = match &table.current_hand_id {
+        Some(id) => hands.find(id).await?,
+        None => None,
+    };
+    Ok(hand.filter(|hand| hand.status == HandStatus::InProgress))
+}
+
+/// How many times a table change is retried when other writers keep saving
+/// the table first.
+const TABLE_RETRIES: usize = 5;
+
+/// Loads a table, applies a change to it and saves it, reloading and
+/// applying the change again if someone else saved the table first.
+pub async fn update_table<F>(
+    tables: &SharedTableRepository,
+    id: &ID,
+    mut change: F,
+) -> Result<Table>
+where
+    F: FnMut(&mut Table) -> Result<(), TableError> + Send,
+{
+    let mut attempts = 0;
+    loop {
+        let mut table = load_table(tables, id).await?;
+        let expected_version = table.version;
+        change(&mut table).map_err(|e| e.extend())?;
+        table.version = expected_version + 1;
+        match tables.save(&table, expected_version).await {
+            Ok(()) => return Ok(table),
+            Err(StorageError::Conflict { .. }) if attempts < TABLE_RETRIES => attempts += 1,
+            Err(e) => return Err(e.extend()),
+        }
+    }
+}
+
+/// Like [`update_table`], but also hands the change the table's current hand
+/// as of the table version it is changing, so a hand dealt in the meantime
+/// is never missed.
+pub async fn update_table_with_hand<F>(
+    tables: &SharedTableRepository,
+    hands: &SharedHandRepository,
+    id: &ID,
+    mut change: F,
+) -> Result<Table>
+where
+    F: FnMut(&mut Table, Option<&Hand>) -> Result<(), TableError> + Send,
+{
+    let mut attempts = 0;
+    loop {
+        let mut table = load_table(tables, id).await?;
+        let current = current_hand(hands, &table).await?;
+        let expected_version = table.version;
+        change(&mut table, current.as_ref()).map_err(|e| e.extend())?;
+        table.version = expected_version + 1;
+        match tables.save(&table, expected_version).await {
+            Ok(()) => return Ok(table),
+            Err(StorageError::Conflict { .. }) if attempts < TABLE_RETRIES => attempts += 1,
+            Err(e) => return Err(e.extend()),
+        }
+    }
+}
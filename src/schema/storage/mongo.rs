@@ -0,0 +1,221 @@
+use async_graphql::{Result, ID};
+use async_trait::async_trait;
+use mongodb::bson::{doc, to_bson, Bson};
+use mongodb::options::{FindOptions, IndexOptions};
+use mongodb::IndexModel;
+use mongodb::{Collection, Database};
+
+use futures::TryStreamExt;
+
+use super::{
+    HandCursor, HandFilter, HandLogRepository, HandRepository, StorageError, TableRepository,
+};
+use crate::bootstrap::schema::model::{Hand, HandLogEntry, Table, TableStatus};
+
+pub struct MongoHandRepository {
+    collection: Collection<Hand>,
+}
+
+impl MongoHandRepository {
+    pub fn new(db: &Database) -> Self {
+        MongoHandRepository {
+            collection: db.collection::<Hand>("hands"),
+        }
+    }
+}
+
+#[async_trait]
+impl HandRepository for MongoHandRepository {
+    async fn insert(&self, hand: &Hand) -> Result<()> {
+        self.collection.insert_one(hand, None).await?;
+        Ok(())
+    }
+
+    async fn find(&self, id: &ID) -> Result<Option<Hand>> {
+        println!("id for mongo: {:#?}", id);
+        Ok(self
+            .collection
+            .find_one(doc! { "id": id.to_string() }, None)
+            .await?)
+    }
+
+    async fn list(
+        &self,
+        filter: &HandFilter,
+        after: Option<&HandCursor>,
+        limit: i64,
+    ) -> Result<Vec<Hand>> {
+        let mut query = doc! {};
+        if let Some(table_id) = &filter.table_id {
+            query.insert("table_id", table_id.to_string());
+        }
+        if let Some(player_id) = &filter.player_id {
+            query.insert("players.id", player_id.to_string());
+        }
+        let mut created_at = doc! {};
+        if let Some(from) = filter.from {
+            created_at.insert("$gte", from);
+        }
+        if let Some(to) = filter.to {
+            created_at.insert("$lt", to);
+        }
+        if !created_at.is_empty() {
+            query.insert("created_at", created_at);
+        }
+        if let Some(status) = filter.status {
+            query.insert("status", to_bson(&status)?);
+        }
+        if let Some(after) = after {
+            query.insert(
+                "$or",
+                vec![
+                    doc! { "created_at": { "$lt": after.created_at } },
+                    doc! { "created_at": after.created_at, "id": { "$lt": after.id.to_string() } },
+                ],
+            );
+        }
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": -1, "id": -1 })
+            .limit(limit)
+            .build();
+        let cursor = self.collection.find(query, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn save(&self, hand: &Hand, expected_version: i64) -> Result<(), StorageError> {
+        println!("before storage");
+        let document = match to_bson(hand)? {
+            Bson::Document(document) => document,
+            _ => {
+                return Err(StorageError::Backend(
+                    "Error converting hand to BSON document".to_string(),
+                ))
+            }
+        };
+        // Hands saved before versioning have no version field.
+        let version = if expected_version == 0 {
+            doc! { "$in": [0_i64, Bson::Null] }
+        } else {
+            doc! { "$eq": expected_version }
+        };
+        let filter = doc! { "id": hand.id.to_string(), "version": version };
+        let update = doc! { "$set": document };
+        let upsert_result = self
+            .collection
+            .find_one_and_update(filter, update, None)
+            .await?;
+        println!("Updated document with ID: {:?}", upsert_result.is_some());
+        if upsert_result.is_some() {
+            return Ok(());
+        }
+        let exists = self
+            .collection
+            .find_one(doc! { "id": hand.id.to_string() }, None)
+            .await?
+            .is_some();
+        if exists {
+            Err(StorageError::Conflict {
+                id: hand.id.clone(),
+                version: expected_version,
+            })
+        } else {
+            Err(StorageError::NotFound(hand.id.clone()))
+        }
+    }
+}
+
+pub struct MongoTableRepository {
+    collection: Collection<Table>,
+}
+
+impl MongoTableRepository {
+    pub fn new(db: &Database) -> Self {
+        MongoTableRepository {
+            collection: db.collection::<Table>("tables"),
+        }
+    }
+}
+
+#[async_trait]
+impl TableRepository for MongoTableRepository {
+    async fn insert(&self, table: &Table) -> Result<()> {
+        self.collection.insert_one(table, None).await?;
+        Ok(())
+    }
+
+    async fn find(&self, id: &ID) -> Result<Option<Table>> {
+        Ok(self
+            .collection
+            .find_one(doc! { "id": id.to_string() }, None)
+            .await?)
+    }
+
+    async fn list(&self, status: TableStatus) -> Result<Vec<Table>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": 1, "id": 1 })
+            .build();
+        let cursor = self
+            .collection
+            .find(doc! { "status": to_bson(&status)? }, options)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn save(&self, table: &Table, expected_version: i64) -> Result<(), StorageError> {
+        let filter = doc! { "id": table.id.to_string(), "version": expected_version };
+        let replaced = self.collection.replace_one(filter, table, None).await?;
+        if replaced.matched_count == 1 {
+            return Ok(());
+        }
+        let exists = self
+            .collection
+            .find_one(doc! { "id": table.id.to_string() }, None)
+            .await?
+            .is_some();
+        if exists {
+            Err(StorageError::Conflict {
+                id: table.id.clone(),
+                version: expected_version,
+            })
+        } else {
+            Err(StorageError::NotFound(table.id.clone()))
+        }
+    }
+}
+
+pub struct MongoHandLogRepository {
+    collection: Collection<HandLogEntry>,
+}
+
+impl MongoHandLogRepository {
+    /// Opens the `hand_events` collection, making sure no two entries for a
+    /// hand share a sequence number.
+    pub async fn new(db: &Database) -> mongodb::error::Result<Self> {
+        let collection = db.collection::<HandLogEntry>("hand_events");
+        let index = IndexModel::builder()
+            .keys(doc! { "hand_id": 1, "sequence": 1 })
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        collection.create_index(index, None).await?;
+        Ok(MongoHandLogRepository { collection })
+    }
+}
+
+#[async_trait]
+impl HandLogRepository for MongoHandLogRepository {
+    async fn find(&self, hand_id: &ID) -> Result<Vec<HandLogEntry>> {
+        let options = FindOptions::builder().sort(doc! { "sequence": 1 }).build();
+        let cursor = self
+            .collection
+            .find(doc! { "hand_id": hand_id.to_string() }, options)
+            .await?;
+        Ok(cursor.try_collect().await?)
+    }
+
+    async fn append(&self, entries: &[HandLogEntry]) -> Result<()> {
+        if !entries.is_empty() {
+            self.collection.insert_many(entries, None).await?;
+        }
+        Ok(())
+    }
+}
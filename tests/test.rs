@@ -3,7 +3,7 @@ mod tests {
     use actix_web::{get, web, App, Error, HttpResponse, Responder};
     use std::fs::File;
     use std::io::Read;
-    use unlimited_poker::bootstrap::{bootstrap, bootstrap_schema};
+    use unlimited_poker::bootstrap::{bootstrap, bootstrap_schema, tokens};
     use url::Url;
     use websocket::client::ClientBuilder;
     use websocket::{Message, OwnedMessage};
@@ -103,6 +103,7 @@ mod tests {
 
     #[actix_web::test]
     async fn test_index_get() {
+        std::env::set_var("STORAGE", "memory");
         let schema = bootstrap_schema().await.unwrap();
         let mut srv = actix_test::start(move || {
             App::new()
@@ -118,9 +119,10 @@ mod tests {
         let json: serde_json::Value =
             serde_json::from_str(&data).expect("JSON was not well-formatted");
 
+        let token = tokens().issue("sean", chrono::Utc::now().timestamp());
         let res = srv
             .post("/graphql")
-            .append_header(("x-user-token", "sean"))
+            .append_header(("x-user-token", token))
             .append_header(("x-table-token", 123))
             .send_json(&json)
             .await